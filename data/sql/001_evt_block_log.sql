@@ -0,0 +1,6 @@
+-- hashes of indexed blocks, used to detect chain reorganizations
+CREATE TABLE IF NOT EXISTS evt_block_log (
+    block_number BIGINT      NOT NULL PRIMARY KEY,
+    block_hash   VARCHAR(66) NOT NULL,
+    block_time   BIGINT      NOT NULL
+);
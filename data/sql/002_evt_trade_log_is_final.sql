@@ -0,0 +1,4 @@
+-- trades are marked final once their block is confirmed, rows indexed before are final already
+ALTER TABLE evt_trade_log ADD COLUMN IF NOT EXISTS is_final BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE evt_trade_log ALTER COLUMN is_final SET DEFAULT FALSE;
+CREATE INDEX IF NOT EXISTS evt_trade_log_not_final_idx ON evt_trade_log (block_number) WHERE NOT is_final;
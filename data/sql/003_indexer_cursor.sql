@@ -0,0 +1,8 @@
+-- last indexed block per contract and chain, written in the same transaction as the indexed rows
+CREATE TABLE IF NOT EXISTS indexer_cursor (
+    contract     VARCHAR(42) NOT NULL,
+    chain_id     BIGINT      NOT NULL,
+    block_number BIGINT      NOT NULL,
+    update_ts    BIGINT      NOT NULL,
+    PRIMARY KEY (contract, chain_id)
+);
@@ -0,0 +1,23 @@
+-- logs whose handler failed, retried with exponential backoff
+CREATE TABLE IF NOT EXISTS evt_failed_log (
+    id            BIGSERIAL   NOT NULL PRIMARY KEY,
+    block_number  BIGINT      NOT NULL,
+    txn_index     BIGINT      NOT NULL,
+    log_index     BIGINT      NOT NULL,
+    block_time    BIGINT      NOT NULL,
+    txn_hash      VARCHAR(66) NOT NULL,
+    address       VARCHAR(42) NOT NULL,
+    topic_0       VARCHAR(66) NOT NULL,
+    topic_1       VARCHAR(66),
+    topic_2       VARCHAR(66),
+    topic_3       VARCHAR(66),
+    data          TEXT,
+    error         TEXT        NOT NULL,
+    attempts      INT         NOT NULL,
+    status        INT         NOT NULL,
+    next_retry_ts BIGINT      NOT NULL,
+    create_ts     BIGINT      NOT NULL,
+    update_ts     BIGINT      NOT NULL,
+    UNIQUE (block_number, txn_index, log_index)
+);
+CREATE INDEX IF NOT EXISTS evt_failed_log_retry_idx ON evt_failed_log (status, next_retry_ts);
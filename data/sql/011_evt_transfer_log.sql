@@ -0,0 +1,28 @@
+-- token transfers and the running balance per user, written by the Transfer handler
+CREATE TABLE IF NOT EXISTS evt_transfer_log (
+    block_number  BIGINT         NOT NULL,
+    txn_index     BIGINT         NOT NULL,
+    log_index     BIGINT         NOT NULL,
+    block_time    BIGINT         NOT NULL,
+    txn_hash      VARCHAR(66)    NOT NULL,
+    token_address VARCHAR(42)    NOT NULL,
+    from_address  VARCHAR(42)    NOT NULL,
+    to_address    VARCHAR(42)    NOT NULL,
+    amount        NUMERIC(78, 18) NOT NULL,
+    PRIMARY KEY (block_number, txn_index, log_index)
+);
+
+CREATE TABLE IF NOT EXISTS evt_balance_log (
+    block_number  BIGINT         NOT NULL,
+    txn_index     BIGINT         NOT NULL,
+    log_index     BIGINT         NOT NULL,
+    user_address  VARCHAR(42)    NOT NULL,
+    token_address VARCHAR(42)    NOT NULL,
+    block_time    BIGINT         NOT NULL,
+    txn_hash      VARCHAR(66)    NOT NULL,
+    delta_amount  NUMERIC(78, 18) NOT NULL,
+    total_amount  NUMERIC(78, 18) NOT NULL,
+    PRIMARY KEY (block_number, txn_index, log_index, user_address)
+);
+CREATE INDEX IF NOT EXISTS evt_balance_log_user_token_idx
+    ON evt_balance_log (user_address, token_address, block_number, txn_index, log_index);
@@ -0,0 +1,14 @@
+-- flags of the indexer that are not a block cursor, e.g. the completed balance ledger
+CREATE TABLE IF NOT EXISTS indexer_state (
+    key       VARCHAR(64)  NOT NULL,
+    value     VARCHAR(128) NOT NULL,
+    update_ts BIGINT       NOT NULL,
+    PRIMARY KEY (key)
+);
+-- the flag was kept as an indexer_cursor row before
+INSERT INTO indexer_state (key, value, update_ts)
+SELECT 'transfer_ledger', block_number::VARCHAR, update_ts
+FROM indexer_cursor
+WHERE contract = 'transfer_ledger'
+ON CONFLICT (key) DO NOTHING;
+DELETE FROM indexer_cursor WHERE contract = 'transfer_ledger';
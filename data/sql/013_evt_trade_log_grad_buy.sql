@@ -0,0 +1,3 @@
+-- PurchaseToGrad is indexed as a buy, tagged and with its fee-inclusive amount
+ALTER TABLE evt_trade_log ADD COLUMN IF NOT EXISTS amount1_with_fee NUMERIC(78, 18);
+ALTER TABLE evt_trade_log ADD COLUMN IF NOT EXISTS is_grad_buy BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,11 @@
+-- history of the factory parameters, a value is in effect for from_block <= block < to_block
+CREATE TABLE IF NOT EXISTS factory_config (
+    param      VARCHAR(64)  NOT NULL,
+    asset      VARCHAR(42)  NOT NULL DEFAULT '',
+    from_block BIGINT       NOT NULL,
+    to_block   BIGINT,
+    value      VARCHAR(128) NOT NULL,
+    block_time BIGINT       NOT NULL,
+    txn_hash   VARCHAR(66)  NOT NULL DEFAULT '',
+    PRIMARY KEY (param, asset, from_block)
+);
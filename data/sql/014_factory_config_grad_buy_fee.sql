@@ -0,0 +1,12 @@
+-- amount1 of the graduation purchases stored before, without the buy fee in effect at their block
+-- runs after 014_factory_config.sql, once the indexer has seeded factory_config on startup;
+-- it is safe to run again, purchases already without the fee are skipped
+UPDATE evt_trade_log t
+SET amount1 = t.amount1_with_fee - t.amount1_with_fee * c.value::NUMERIC / (10000 + c.value::NUMERIC)
+FROM factory_config c
+WHERE t.is_grad_buy
+  AND t.amount1 = t.amount1_with_fee
+  AND c.param = 'buy_fee'
+  AND c.asset = ''
+  AND c.from_block <= t.block_number
+  AND (c.to_block IS NULL OR c.to_block > t.block_number);
@@ -0,0 +1,3 @@
+-- raised tokens are registered from the factory whitelist events, rows added by hand were whitelisted
+ALTER TABLE raised_token ADD COLUMN IF NOT EXISTS is_whitelisted BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE raised_token ALTER COLUMN is_whitelisted SET DEFAULT FALSE;
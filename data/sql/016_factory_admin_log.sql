@@ -0,0 +1,16 @@
+-- role, ownership, initialization and upgrade events of the factory
+CREATE TABLE IF NOT EXISTS factory_admin_log (
+    block_number BIGINT      NOT NULL,
+    txn_index    BIGINT      NOT NULL,
+    log_index    BIGINT      NOT NULL,
+    block_time   BIGINT      NOT NULL,
+    txn_hash     VARCHAR(66) NOT NULL,
+    event        VARCHAR(32) NOT NULL,
+    role         VARCHAR(66),
+    account      VARCHAR(42),
+    sender       VARCHAR(42),
+    prev_value   VARCHAR(66),
+    new_value    VARCHAR(66),
+    PRIMARY KEY (block_number, txn_index, log_index)
+);
+CREATE INDEX IF NOT EXISTS idx_factory_admin_log_event ON factory_admin_log (event, block_number);
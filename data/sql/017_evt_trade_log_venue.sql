@@ -0,0 +1,2 @@
+-- trades on the Uniswap V2 pair after graduation are indexed next to the bonding curve trades
+ALTER TABLE evt_trade_log ADD COLUMN IF NOT EXISTS venue VARCHAR(16) NOT NULL DEFAULT 'curve';
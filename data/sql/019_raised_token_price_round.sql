@@ -0,0 +1,4 @@
+-- raised token prices follow the AnswerUpdated events of the oracle aggregators
+ALTER TABLE raised_token ADD COLUMN IF NOT EXISTS aggregator VARCHAR(42) NOT NULL DEFAULT '';
+ALTER TABLE raised_token ADD COLUMN IF NOT EXISTS price_round BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE raised_token ADD COLUMN IF NOT EXISTS price_ts BIGINT NOT NULL DEFAULT 0;
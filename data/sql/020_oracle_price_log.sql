@@ -0,0 +1,10 @@
+-- rounds of the raised tokens' oracles, trades are priced at the round active at their block time
+CREATE TABLE IF NOT EXISTS oracle_price_log (
+    oracle     VARCHAR(42)     NOT NULL,
+    aggregator VARCHAR(42)     NOT NULL,
+    round_id   BIGINT          NOT NULL,
+    answer     NUMERIC(78, 18) NOT NULL,
+    updated_at BIGINT          NOT NULL,
+    PRIMARY KEY (oracle, aggregator, round_id)
+);
+CREATE INDEX IF NOT EXISTS idx_oracle_price_log_updated_at ON oracle_price_log (oracle, updated_at);
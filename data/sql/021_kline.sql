@@ -0,0 +1,15 @@
+-- candles of every PeriodType, kline_5m is kept for the 24h statistics
+CREATE TABLE IF NOT EXISTS kline (
+    token_address VARCHAR(42)     NOT NULL,
+    period        VARCHAR(8)      NOT NULL,
+    open_ts       BIGINT          NOT NULL,
+    close_ts      BIGINT          NOT NULL,
+    open          NUMERIC(78, 18) NOT NULL,
+    high          NUMERIC(78, 18) NOT NULL,
+    low           NUMERIC(78, 18) NOT NULL,
+    close         NUMERIC(78, 18) NOT NULL,
+    volume        NUMERIC(78, 18) NOT NULL,
+    amount        NUMERIC(78, 18) NOT NULL,
+    txn_num       BIGINT          NOT NULL,
+    PRIMARY KEY (token_address, period, open_ts)
+);
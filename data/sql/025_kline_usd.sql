@@ -0,0 +1,28 @@
+-- USD candles next to the raised token candles. Existing candles are left at 0, run
+-- `cargo run -- kline rebuild all` right after this file and before the indexer is restarted,
+-- otherwise trades keep the low_usd of 0 as the candle low
+ALTER TABLE kline_5m ADD COLUMN IF NOT EXISTS open_usd NUMERIC(78, 18) NOT NULL DEFAULT 0;
+ALTER TABLE kline_5m ADD COLUMN IF NOT EXISTS high_usd NUMERIC(78, 18) NOT NULL DEFAULT 0;
+ALTER TABLE kline_5m ADD COLUMN IF NOT EXISTS low_usd NUMERIC(78, 18) NOT NULL DEFAULT 0;
+ALTER TABLE kline_5m ADD COLUMN IF NOT EXISTS close_usd NUMERIC(78, 18) NOT NULL DEFAULT 0;
+ALTER TABLE kline_5m ADD COLUMN IF NOT EXISTS amount_usd NUMERIC(78, 18) NOT NULL DEFAULT 0;
+ALTER TABLE kline ADD COLUMN IF NOT EXISTS open_usd NUMERIC(78, 18) NOT NULL DEFAULT 0;
+ALTER TABLE kline ADD COLUMN IF NOT EXISTS high_usd NUMERIC(78, 18) NOT NULL DEFAULT 0;
+ALTER TABLE kline ADD COLUMN IF NOT EXISTS low_usd NUMERIC(78, 18) NOT NULL DEFAULT 0;
+ALTER TABLE kline ADD COLUMN IF NOT EXISTS close_usd NUMERIC(78, 18) NOT NULL DEFAULT 0;
+ALTER TABLE kline ADD COLUMN IF NOT EXISTS amount_usd NUMERIC(78, 18) NOT NULL DEFAULT 0;
+
+-- launch price in USD, existing launches are priced at the stored oracle round active then
+ALTER TABLE evt_token_log ADD COLUMN IF NOT EXISTS init_price_usd NUMERIC(78, 18);
+UPDATE evt_token_log l
+SET init_price_usd = l.init_price * COALESCE((
+    SELECT o.answer FROM oracle_price_log o
+    WHERE o.oracle = r.oracle AND o.updated_at <= l.block_time
+    ORDER BY o.updated_at DESC
+    LIMIT 1
+), r.price)
+FROM raised_token r
+WHERE r.address = l.raised_address AND l.init_price_usd IS NULL;
+UPDATE evt_token_log SET init_price_usd = 0 WHERE init_price_usd IS NULL;
+ALTER TABLE evt_token_log ALTER COLUMN init_price_usd SET DEFAULT 0;
+ALTER TABLE evt_token_log ALTER COLUMN init_price_usd SET NOT NULL;
@@ -0,0 +1,99 @@
+use sea_orm::prelude::Expr;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder};
+
+use crate::core::Store;
+use crate::entity::db_evt_failed_log::{
+    STATUS_DISCARDED, STATUS_EXHAUSTED, STATUS_PENDING, STATUS_RESOLVED,
+};
+use crate::entity::*;
+use crate::evt::Evt;
+use crate::util::{LibError, LibResult};
+
+pub async fn list(store: &Store) -> LibResult<()> {
+    let failed_logs = db_evt_failed_log::Entity::find()
+        .filter(db_evt_failed_log::Column::Status.is_in([STATUS_PENDING, STATUS_EXHAUSTED]))
+        .order_by_asc(db_evt_failed_log::Column::Id)
+        .all(&store.db_pool)
+        .await?;
+    for v in failed_logs.iter() {
+        println!(
+            "id={} status={} attempts={} next_retry_ts={} block={} txn_hash={} log_index={} topic={} error={}",
+            v.id,
+            status_name(v.status),
+            v.attempts,
+            v.next_retry_ts,
+            v.block_number,
+            v.txn_hash,
+            v.log_index,
+            v.topic_0,
+            v.error
+        );
+    }
+    println!("{} failed events", failed_logs.len());
+    Ok(())
+}
+
+pub async fn retry(store: Store, id: i64) -> LibResult<()> {
+    let failed = db_evt_failed_log::Entity::find_by_id(id)
+        .one(&store.db_pool)
+        .await?
+        .ok_or_else(|| LibError::InternalError(format!("failed event {id} not found")))?;
+    if failed.status != STATUS_PENDING && failed.status != STATUS_EXHAUSTED {
+        return Err(LibError::InternalError(format!(
+            "failed event {id} is {}",
+            status_name(failed.status)
+        )));
+    }
+    let success = Evt::new(store).retry_failed_log(failed).await?;
+    println!("id={id} retry success={success}");
+    Ok(())
+}
+
+pub async fn retry_all(store: Store) -> LibResult<()> {
+    let failed_logs = db_evt_failed_log::Entity::find()
+        .filter(db_evt_failed_log::Column::Status.is_in([STATUS_PENDING, STATUS_EXHAUSTED]))
+        .order_by_asc(db_evt_failed_log::Column::BlockNumber)
+        .order_by_asc(db_evt_failed_log::Column::TxnIndex)
+        .order_by_asc(db_evt_failed_log::Column::LogIndex)
+        .all(&store.db_pool)
+        .await?;
+    let evt = Evt::new(store);
+    for failed in failed_logs {
+        let id = failed.id;
+        let success = evt.retry_failed_log(failed).await?;
+        println!("id={id} retry success={success}");
+    }
+    Ok(())
+}
+
+pub async fn discard(store: &Store, id: i64) -> LibResult<()> {
+    let re = db_evt_failed_log::Entity::update_many()
+        .filter(db_evt_failed_log::Column::Id.eq(id))
+        .col_expr(
+            db_evt_failed_log::Column::Status,
+            Expr::value(STATUS_DISCARDED),
+        )
+        .col_expr(
+            db_evt_failed_log::Column::UpdateTs,
+            Expr::value(chrono::Utc::now().timestamp()),
+        )
+        .exec(&store.db_pool)
+        .await?;
+    if re.rows_affected == 0 {
+        return Err(LibError::InternalError(format!(
+            "failed event {id} not found"
+        )));
+    }
+    println!("id={id} discarded");
+    Ok(())
+}
+
+fn status_name(status: i32) -> &'static str {
+    match status {
+        STATUS_PENDING => "pending",
+        STATUS_RESOLVED => "resolved",
+        STATUS_DISCARDED => "discarded",
+        STATUS_EXHAUSTED => "exhausted",
+        _ => "unknown",
+    }
+}
@@ -0,0 +1,89 @@
+use sea_orm::TransactionTrait;
+
+use crate::core::Store;
+use crate::evt::repair_klines;
+use crate::svc::{kline_series, market_cap_series};
+use crate::util::{LibResult, PeriodType};
+
+/// Rebuild the candles of `token`, or of all tokens for `all`, from `from_ts` up to `to_ts`
+/// and print what was corrected.
+pub async fn rebuild(store: Store, token: &str, from_ts: i64, to_ts: Option<i64>) -> LibResult<()> {
+    let token = (token != "all").then(|| token.to_lowercase());
+    let tx = store.db_pool.begin().await?;
+    let diffs = repair_klines(&tx, token.as_deref(), from_ts, to_ts).await?;
+    tx.commit().await?;
+    for v in diffs.iter() {
+        println!(
+            "table={} period={} added={} changed={} removed={}",
+            v.table,
+            v.period.name(),
+            v.added,
+            v.changed,
+            v.removed
+        );
+    }
+    let corrected: i64 = diffs.iter().map(|v| v.added + v.changed + v.removed).sum();
+    println!("{corrected} candles corrected");
+    Ok(())
+}
+
+/// Print the gap-filled candles of `token` for a TradingView `resolution` from `from_ts` up to
+/// `to_ts`, now by default.
+pub async fn series(
+    store: &Store,
+    token: &str,
+    resolution: &str,
+    from_ts: i64,
+    to_ts: Option<i64>,
+) -> LibResult<()> {
+    let period: PeriodType = resolution.parse()?;
+    let to_ts = to_ts.unwrap_or_else(|| chrono::Utc::now().timestamp());
+    let token = token.to_lowercase();
+    let series = kline_series(&store.db_pool, &token, period, from_ts, to_ts).await?;
+    for v in series.iter() {
+        println!(
+            "open_ts={} open={} high={} low={} close={} volume={} txn_num={} \
+            open_usd={} high_usd={} low_usd={} close_usd={} amount_usd={}",
+            v.open_ts,
+            v.open,
+            v.high,
+            v.low,
+            v.close,
+            v.volume,
+            v.txn_num,
+            v.open_usd,
+            v.high_usd,
+            v.low_usd,
+            v.close_usd,
+            v.amount_usd
+        );
+    }
+    println!("{} candles of {}", series.len(), period.resolution());
+    Ok(())
+}
+
+/// Print the gap-filled USD market-cap candles of `token`, like `series`.
+pub async fn market_cap(
+    store: &Store,
+    token: &str,
+    resolution: &str,
+    from_ts: i64,
+    to_ts: Option<i64>,
+) -> LibResult<()> {
+    let period: PeriodType = resolution.parse()?;
+    let to_ts = to_ts.unwrap_or_else(|| chrono::Utc::now().timestamp());
+    let token = token.to_lowercase();
+    let series = market_cap_series(&store.db_pool, &token, period, from_ts, to_ts).await?;
+    for v in series.iter() {
+        println!(
+            "open_ts={} open={} high={} low={} close={}",
+            v.open_ts, v.open, v.high, v.low, v.close
+        );
+    }
+    println!(
+        "{} market cap candles of {}",
+        series.len(),
+        period.resolution()
+    );
+    Ok(())
+}
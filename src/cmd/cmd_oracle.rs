@@ -0,0 +1,34 @@
+use sea_orm::{EntityTrait, QueryOrder, TransactionTrait};
+
+use crate::core::Store;
+use crate::entity::*;
+use crate::evt::{backfill_rounds, repair_klines, reprice_trades};
+use crate::util::LibResult;
+
+/// Store the oracle rounds since `from_ts`, by default since the first trade, reprice the
+/// trades made since then at the round active at their block time and rebuild their candles.
+pub async fn backfill(store: Store, from_ts: Option<i64>) -> LibResult<()> {
+    let from_ts = match from_ts {
+        Some(v) => v,
+        None => {
+            let first_trade = db_evt_trade_log::Entity::find()
+                .order_by_asc(db_evt_trade_log::Column::BlockTime)
+                .one(&store.db_pool)
+                .await?;
+            match first_trade {
+                Some(v) => v.block_time,
+                None => {
+                    println!("no trades to price");
+                    return Ok(());
+                }
+            }
+        }
+    };
+    let rounds = backfill_rounds(&store.db_pool, from_ts).await?;
+    let trades = reprice_trades(&store.db_pool, from_ts).await?;
+    let tx = store.db_pool.begin().await?;
+    repair_klines(&tx, None, from_ts, None).await?;
+    tx.commit().await?;
+    println!("{rounds} oracle rounds backfilled from {from_ts}, {trades} trades repriced");
+    Ok(())
+}
@@ -0,0 +1,9 @@
+use crate::core::Store;
+use crate::evt::Evt;
+use crate::util::LibResult;
+
+pub async fn backfill(store: Store, from_block: u64) -> LibResult<()> {
+    Evt::new(store).backfill_pairs(from_block).await?;
+    println!("pair swaps backfilled from block {from_block}");
+    Ok(())
+}
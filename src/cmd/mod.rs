@@ -0,0 +1,57 @@
+mod cmd_failed;
+mod cmd_kline;
+mod cmd_oracle;
+mod cmd_pair;
+mod cmd_transfer;
+
+use crate::core::{consts, Store};
+use crate::util::{LibError, LibResult};
+
+const USAGE: &str = "usage:
+    maxfun-evt                          run the event monitor
+    maxfun-evt failed list              list failed events waiting for a retry or given up
+    maxfun-evt failed retry <id|all>    retry failed events now
+    maxfun-evt failed discard <id>      discard a failed event
+    maxfun-evt transfer backfill [from] index token transfers up to the cursor, from INIT_BLOCK by default
+    maxfun-evt pair backfill [from]     index swaps on graduated pairs up to the cursor, from INIT_BLOCK by default
+    maxfun-evt oracle backfill [from_ts] store oracle rounds, reprice trades and candles, from the first trade by default
+    maxfun-evt kline rebuild <token|all> [from_ts] [to_ts]  recompute candles from the trades, report corrections
+    maxfun-evt kline series <token> <resolution> <from_ts> [to_ts]  print gap-filled candles, e.g. resolution 15, 1H, 1D
+    maxfun-evt kline mcap <token> <resolution> <from_ts> [to_ts]    print gap-filled USD market cap candles";
+
+/// run an operator command given on the command line
+pub async fn run(store: Store, args: &[String]) -> LibResult<()> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["failed", "list"] => cmd_failed::list(&store).await,
+        ["failed", "retry", "all"] => cmd_failed::retry_all(store).await,
+        ["failed", "retry", id] => cmd_failed::retry(store, id.parse()?).await,
+        ["failed", "discard", id] => cmd_failed::discard(&store, id.parse()?).await,
+        ["transfer", "backfill"] => cmd_transfer::backfill(store, *consts::INIT_BLOCK).await,
+        ["transfer", "backfill", from] => cmd_transfer::backfill(store, from.parse()?).await,
+        ["pair", "backfill"] => cmd_pair::backfill(store, *consts::INIT_BLOCK).await,
+        ["pair", "backfill", from] => cmd_pair::backfill(store, from.parse()?).await,
+        ["oracle", "backfill"] => cmd_oracle::backfill(store, None).await,
+        ["oracle", "backfill", from] => cmd_oracle::backfill(store, Some(from.parse()?)).await,
+        ["kline", "rebuild", token] => cmd_kline::rebuild(store, token, 0, None).await,
+        ["kline", "rebuild", token, from] => {
+            cmd_kline::rebuild(store, token, from.parse()?, None).await
+        }
+        ["kline", "rebuild", token, from, to] => {
+            cmd_kline::rebuild(store, token, from.parse()?, Some(to.parse()?)).await
+        }
+        ["kline", "series", token, resolution, from] => {
+            cmd_kline::series(&store, token, resolution, from.parse()?, None).await
+        }
+        ["kline", "series", token, resolution, from, to] => {
+            cmd_kline::series(&store, token, resolution, from.parse()?, Some(to.parse()?)).await
+        }
+        ["kline", "mcap", token, resolution, from] => {
+            cmd_kline::market_cap(&store, token, resolution, from.parse()?, None).await
+        }
+        ["kline", "mcap", token, resolution, from, to] => {
+            cmd_kline::market_cap(&store, token, resolution, from.parse()?, Some(to.parse()?)).await
+        }
+        _ => Err(LibError::InternalError(USAGE.to_string())),
+    }
+}
@@ -1,4 +1,5 @@
 use std::sync::LazyLock;
+use alloy::eips::BlockNumberOrTag;
 use alloy::sol;
 
 pub static PG_URL: LazyLock<String> = LazyLock::new(||
@@ -8,11 +9,30 @@ pub static REDIS_URL: LazyLock<String> = LazyLock::new(||
     std::env::var("REDIS_URL").expect("env not found REDIS_URL")
 );
 
-/// bsc rpc provider
-pub static PROVIDER: LazyLock<String> = LazyLock::new(||
-    std::env::var("PROVIDER").expect("env not found PROVIDER")
+/// rpc providers, comma separated, requests are routed to the healthiest one
+pub static PROVIDER: LazyLock<Vec<String>> = LazyLock::new(||
+    std::env::var("PROVIDER").expect("env not found PROVIDER").split(',').map(|v| v.trim().to_string()).filter(|v| !v.is_empty()).collect()
 );
 
+/// cross-check `get_logs` results with a second provider
+pub static RPC_CROSS_CHECK: LazyLock<bool> = LazyLock::new(||
+    std::env::var("RPC_CROSS_CHECK").map(|v| v.parse().expect("parse error RPC_CROSS_CHECK")).unwrap_or(false)
+);
+
+/// websocket rpc provider, when set new logs are followed with `eth_subscribe` instead of polling
+pub static WS_PROVIDER: LazyLock<Option<String>> = LazyLock::new(||
+    std::env::var("WS_PROVIDER").ok().filter(|v| !v.is_empty())
+);
+
+/// webhook security alerts are posted to, alerts are only logged when empty
+pub static ALERT_WEBHOOK: LazyLock<Option<String>> = LazyLock::new(||
+    std::env::var("ALERT_WEBHOOK").ok().filter(|v| !v.is_empty())
+);
+
+/// hours east of UTC the candles of every period are aligned in, as the log timestamps
+pub static KLINE_TZ_OFFSET: LazyLock<i64> = LazyLock::new(||
+    std::env::var("KLINE_TZ_OFFSET").map(|v| v.parse::<i64>().expect("parse error KLINE_TZ_OFFSET")).unwrap_or(8) * 3600
+);
 
 pub static FACTORY_CONTRACT_ADDR: LazyLock<String> = LazyLock::new(||
     std::env::var("FACTORY_CONTRACT_ADDR").expect("env not found FACTORY_CONTRACT_ADDR")
@@ -22,6 +42,21 @@ pub static INIT_BLOCK: LazyLock<u64> = LazyLock::new(||
     std::env::var("INIT_BLOCK").expect("env not found INIT_BLOCK").parse().expect("parse error INIT_BLOCK")
 );
 
+/// head a block has to be behind to count as confirmed: latest, safe or finalized
+pub static CONFIRM_HEAD: LazyLock<BlockNumberOrTag> = LazyLock::new(||
+    std::env::var("CONFIRM_HEAD").map(|v| v.parse().expect("parse error CONFIRM_HEAD")).unwrap_or(BlockNumberOrTag::Latest)
+);
+
+/// number of blocks a block has to be behind `CONFIRM_HEAD` to count as confirmed
+pub static CONFIRM_DEPTH: LazyLock<u64> = LazyLock::new(||
+    std::env::var("CONFIRM_DEPTH").map(|v| v.parse().expect("parse error CONFIRM_DEPTH")).unwrap_or(0)
+);
+
+/// index up to the latest block and mark rows final once they are confirmed,
+/// instead of only indexing confirmed blocks
+pub static INDEX_UNCONFIRMED: LazyLock<bool> = LazyLock::new(||
+    std::env::var("INDEX_UNCONFIRMED").map(|v| v.parse().expect("parse error INDEX_UNCONFIRMED")).unwrap_or(false)
+);
 
 // pub const FACTORY_ABI_FILE: &str = "data/abi/MaxFunFactory.json";
 // pub const MANAGER_ABI_FILE: &str = "data/abi/MaxFunManager.json";
@@ -29,7 +64,48 @@ pub static INIT_BLOCK: LazyLock<u64> = LazyLock::new(||
 pub const GAP_BLOCK: u64 = 5;
 pub const POLL_INTERVAL: u64 = 5;
 pub const MAX_BLOCK_RANGE: u64 = 10000;
+/// providers more than this many blocks behind the highest head are used last
+pub const RPC_MAX_HEAD_LAG: u64 = 3;
+/// seconds to poll after the websocket dropped before reconnecting
+pub const WS_RECONNECT_INTERVAL: u64 = 60;
 pub const PK_BLOCK_NUM: &str = "block_num";
+/// failed events are retried after `RETRY_BASE_DELAY * 2^(attempts - 1)` seconds
+pub const RETRY_BASE_DELAY: i64 = 60;
+pub const RETRY_MAX_DELAY: i64 = 86400;
+pub const MAX_RETRY_ATTEMPTS: i32 = 10;
+/// highest indexed block that is confirmed, for frontends
+pub const PK_FINAL_BLOCK_NUM: &str = "final_block_num";
+/// token or pair addresses per `get_logs` request for Transfer, Swap and Sync logs
+pub const TRANSFER_ADDRESS_BATCH: usize = 500;
+/// `buy_fee` and `sell_fee` of the factory are in basis points
+pub const FEE_DENOMINATOR: u64 = 10000;
+/// `evt_trade_log.venue` of trades on the bonding curve
+pub const VENUE_CURVE: &str = "curve";
+/// `evt_trade_log.venue` of trades on the Uniswap V2 pair after graduation
+pub const VENUE_UNISWAP_V2: &str = "uniswap_v2";
+/// user balances compared with the chain per spot-check
+pub const BALANCE_CHECK_SIZE: u64 = 20;
+/// seconds an admin event may be old and still raise its own alert, older ones, e.g. while
+/// catching up, are sent together as one alert
+pub const ALERT_MAX_AGE: i64 = 3600;
+/// factory roles, and whether their changes raise a high-severity alert
+pub const FACTORY_ROLES: [(&str, bool); 5] = [
+    ("DEFAULT_ADMIN_ROLE", true),
+    ("ADMIN_ROLE", true),
+    ("FEE_MANAGER_ROLE", true),
+    ("ADDR_MANAGER_ROLE", true),
+    ("WHITELIST_MANAGER_ROLE", false),
+];
+/// block headers requested per batch request
+pub const BLOCK_BATCH_SIZE: usize = 100;
+/// swap transactions requested per batch request
+pub const TXN_BATCH_SIZE: usize = 100;
+/// block headers kept in memory
+pub const BLOCK_CACHE_SIZE: usize = 1024;
+/// how many blocks of hashes are kept in evt_block_log for reorg detection
+pub const REORG_DEPTH: u64 = 128;
+/// candles a gap-filled series may have, longer spans are rejected
+pub const KLINE_SERIES_MAX: usize = 5000;
 
 
 sol!(
@@ -40,12 +116,25 @@ sol!(
 );
 
 sol!(
-    #[allow(missing_docs)]
+    #[allow(missing_docs, clippy::too_many_arguments)]
     #[sol(rpc)]
     FACTORY,
     "data/abi/Factory-0225.json"
 );
 
+sol!(
+    #[allow(missing_docs)]
+    #[sol(rpc)]
+    PAIR,
+    "data/abi/UniswapV2Pair.json"
+);
+
+sol!(
+    #[allow(missing_docs)]
+    PROXY,
+    "data/abi/Proxy.json"
+);
+
 sol!(
     #[allow(missing_docs)]
     #[sol(rpc)]
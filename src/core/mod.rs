@@ -1,4 +1,6 @@
 pub mod consts;
 pub mod pool;
+pub mod rpc;
 
-pub use pool::Store;
\ No newline at end of file
+pub use pool::Store;
+pub use rpc::RPC;
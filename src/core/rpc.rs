@@ -0,0 +1,232 @@
+use std::sync::{Arc, LazyLock, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use alloy::network::Ethereum;
+use alloy::providers::{Provider, RootProvider};
+use alloy::rpc::client::RpcClient;
+use alloy::rpc::json_rpc::{RequestPacket, ResponsePacket};
+use alloy::transports::http::reqwest::Url;
+use alloy::transports::http::ReqwestTransport;
+use alloy::transports::{TransportError, TransportFut};
+use tower::Service;
+
+use super::consts;
+
+/// rpc providers shared by the indexer and `svc::TOKEN`
+pub static RPC: LazyLock<RpcPool> = LazyLock::new(|| RpcPool::new(&consts::PROVIDER));
+
+/// weight of the newest sample in the moving averages
+const EWMA_WEIGHT: f64 = 0.2;
+
+/// Pool of rpc endpoints that is used as the transport of a provider.
+///
+/// Every request goes to the healthiest endpoint and fails over to the next one on a
+/// transport error (http error, rate limit, timeout). Json-rpc errors are returned as they
+/// are, they are answers of the node and not a problem of the endpoint.
+#[derive(Clone)]
+pub struct RpcPool {
+    endpoints: Arc<Vec<Endpoint>>,
+}
+
+struct Endpoint {
+    url: String,
+    transport: ReqwestTransport,
+    provider: RootProvider<Ethereum>,
+    health: Mutex<Health>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Health {
+    /// moving average of the response time in ms
+    latency: f64,
+    /// moving average of failed requests, 0 to 1
+    error_rate: f64,
+    /// latest block number seen by the health check
+    head: u64,
+}
+
+impl RpcPool {
+    pub fn new(urls: &[String]) -> Self {
+        assert!(!urls.is_empty(), "env not found PROVIDER");
+        let endpoints = urls
+            .iter()
+            .map(|url| {
+                let parsed: Url = url.parse().expect("Could not parse the Provider URL");
+                Endpoint {
+                    url: url.clone(),
+                    transport: ReqwestTransport::new(parsed.clone()),
+                    provider: RootProvider::new_http(parsed),
+                    health: Mutex::new(Health::default()),
+                }
+            })
+            .collect();
+        Self {
+            endpoints: Arc::new(endpoints),
+        }
+    }
+
+    /// provider that routes every request through the pool
+    pub fn provider(&self) -> RootProvider<Ethereum> {
+        RootProvider::new(RpcClient::new(self.clone(), false))
+    }
+
+    /// Provider of the second healthiest endpoint, to cross-check what the pool returned.
+    ///
+    /// `None` if the pool has a single endpoint.
+    pub fn alternate(&self) -> Option<RootProvider<Ethereum>> {
+        let ranked = self.ranked();
+        ranked
+            .get(1)
+            .map(|idx| self.endpoints[*idx].provider.clone())
+    }
+
+    /// Probe the head block and latency of every endpoint.
+    pub async fn check_health(&self) {
+        for endpoint in self.endpoints.iter() {
+            let started = Instant::now();
+            match endpoint.provider.get_block_number().await {
+                Ok(head) => {
+                    let mut health = endpoint.health.lock().unwrap();
+                    health.record(started.elapsed(), true);
+                    health.head = head;
+                }
+                Err(e) => {
+                    tracing::warn!("rpc health check failed. url={}, err={e}", endpoint.url);
+                    endpoint
+                        .health
+                        .lock()
+                        .unwrap()
+                        .record(started.elapsed(), false);
+                }
+            }
+        }
+        tracing::debug!("rpc health: {:?}", self.healths());
+    }
+
+    fn healths(&self) -> Vec<Health> {
+        self.endpoints
+            .iter()
+            .map(|v| v.health.lock().unwrap().clone())
+            .collect()
+    }
+
+    fn ranked(&self) -> Vec<usize> {
+        rank(&self.healths())
+    }
+
+    async fn request(self, req: RequestPacket) -> Result<ResponsePacket, TransportError> {
+        let mut last_err = None;
+        for idx in self.ranked() {
+            let endpoint = &self.endpoints[idx];
+            let started = Instant::now();
+            let mut transport = endpoint.transport.clone();
+            let re = transport.call(req.clone()).await;
+            endpoint
+                .health
+                .lock()
+                .unwrap()
+                .record(started.elapsed(), re.is_ok());
+            match re {
+                Ok(resp) => return Ok(resp),
+                Err(e) => {
+                    tracing::warn!("rpc request failed. url={}, err={e}", endpoint.url);
+                    last_err = Some(e);
+                }
+            }
+        }
+        Err(last_err.unwrap())
+    }
+}
+
+impl Service<RequestPacket> for RpcPool {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: RequestPacket) -> Self::Future {
+        Box::pin(self.clone().request(req))
+    }
+}
+
+impl Health {
+    fn record(&mut self, elapsed: Duration, ok: bool) {
+        let latency = elapsed.as_secs_f64() * 1000.0;
+        let failed = if ok { 0.0 } else { 1.0 };
+        if self.latency == 0.0 {
+            self.latency = latency;
+        } else {
+            self.latency += (latency - self.latency) * EWMA_WEIGHT;
+        }
+        self.error_rate += (failed - self.error_rate) * EWMA_WEIGHT;
+    }
+
+    /// lower is better, every 10% of failed requests count like doubling the latency
+    fn score(&self) -> f64 {
+        self.latency.max(1.0) * (1.0 + 10.0 * self.error_rate)
+    }
+}
+
+/// Endpoint indexes from the healthiest to the least healthy.
+///
+/// Endpoints more than `RPC_MAX_HEAD_LAG` blocks behind the highest head come last,
+/// the rest are ordered by latency weighted with the error rate.
+fn rank(healths: &[Health]) -> Vec<usize> {
+    let max_head = healths.iter().map(|v| v.head).max().unwrap_or_default();
+    let mut ranked: Vec<usize> = (0..healths.len()).collect();
+    ranked.sort_by(|a, b| {
+        let (a, b) = (&healths[*a], &healths[*b]);
+        let a_lag = max_head - a.head > consts::RPC_MAX_HEAD_LAG;
+        let b_lag = max_head - b.head > consts::RPC_MAX_HEAD_LAG;
+        a_lag.cmp(&b_lag).then(a.score().total_cmp(&b.score()))
+    });
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn health(latency: f64, error_rate: f64, head: u64) -> Health {
+        Health {
+            latency,
+            error_rate,
+            head,
+        }
+    }
+
+    #[test]
+    fn t_rank() {
+        // fastest first
+        let healths = [health(200.0, 0.0, 100), health(50.0, 0.0, 100)];
+        assert_eq!(rank(&healths), vec![1, 0]);
+
+        // errors outweigh latency
+        let healths = [health(200.0, 0.0, 100), health(50.0, 0.5, 100)];
+        assert_eq!(rank(&healths), vec![0, 1]);
+
+        // lagging endpoints last, a small lag is fine
+        let healths = [
+            health(10.0, 0.0, 90),
+            health(200.0, 0.0, 100),
+            health(100.0, 0.0, 99),
+        ];
+        assert_eq!(rank(&healths), vec![2, 1, 0]);
+    }
+
+    #[test]
+    fn t_health_record() {
+        let mut health = Health::default();
+        health.record(Duration::from_millis(100), true);
+        assert_eq!(health.latency, 100.0);
+        assert_eq!(health.error_rate, 0.0);
+
+        health.record(Duration::from_millis(200), false);
+        assert_eq!(health.latency, 120.0);
+        assert!((health.error_rate - 0.2).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,20 @@
+use crate::core::Store;
+use crate::evt::Evt;
+use crate::util::LibResult;
+
+/// compares a sample of the balances derived from the transfer ledger with the chain
+pub struct CronBalance {
+    evt: Evt,
+}
+
+impl CronBalance {
+    pub fn new(store: Store) -> Self {
+        Self {
+            evt: Evt::new(store),
+        }
+    }
+
+    pub async fn run(&self) -> LibResult<()> {
+        self.evt.check_balances().await
+    }
+}
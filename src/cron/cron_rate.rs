@@ -1,11 +1,12 @@
 use std::ops::{Div, Sub};
 
 use rust_decimal::Decimal;
-use sea_orm::{ColumnTrait, EntityTrait, QuerySelect, QueryFilter, QueryOrder};
+use sea_orm::{ColumnTrait, EntityTrait, QuerySelect, QueryFilter};
 use sea_orm::prelude::Expr;
 
 use crate::core::Store;
 use crate::entity::*;
+use crate::svc::close_5m_at;
 use crate::util::LibResult;
 
 pub struct CronRate {
@@ -37,13 +38,6 @@ impl CronRate {
         let now_ts = chrono::Utc::now().timestamp();
         let start_ts = now_ts - now_ts % 300;
         let end_ts = start_ts - 3600 * 24;
-        let last_kline = db_kline_5m::Entity::find()
-            .filter(db_kline_5m::Column::TokenAddress.eq(token))
-            .filter(db_kline_5m::Column::OpenTs.lte(end_ts))
-            .order_by_desc(db_kline_5m::Column::OpenTs)
-            .limit(1)
-            .one(&self.store.db_pool)
-            .await?;
         let volume_24h: Decimal = db_kline_5m::Entity::find()
             .filter(db_kline_5m::Column::TokenAddress.eq(token))
             .filter(db_kline_5m::Column::OpenTs.gte(end_ts))
@@ -55,14 +49,10 @@ impl CronRate {
             .unwrap_or(Some(Decimal::ZERO))
             .unwrap_or(Decimal::ZERO);
 
-        let last_price = if let Some(kline) = last_kline {
-            kline.close
-        } else {
-            db_kline_5m::Entity::find()
-                .filter(db_kline_5m::Column::TokenAddress.eq(token))
-                .order_by_asc(db_kline_5m::Column::OpenTs)
-                .limit(1).one(&self.store.db_pool).await?.unwrap().close
-        };
+        // close of the gap-filled series 24h ago, the launch price for younger tokens
+        let last_price = close_5m_at(&self.store.db_pool, token, end_ts)
+            .await?
+            .unwrap_or(price);
 
         let last_price = if last_price == Decimal::ZERO {
             Decimal::new(1, 18)
@@ -0,0 +1,20 @@
+use crate::core::Store;
+use crate::evt::Evt;
+use crate::util::LibResult;
+
+/// retries the events whose handling failed, see `evt_failed_log`
+pub struct CronRetry {
+    evt: Evt,
+}
+
+impl CronRetry {
+    pub fn new(store: Store) -> Self {
+        Self {
+            evt: Evt::new(store),
+        }
+    }
+
+    pub async fn run(&self) -> LibResult<()> {
+        self.evt.retry_failed().await
+    }
+}
@@ -1,9 +1,11 @@
+mod cron_balance;
 mod cron_price;
 mod cron_rate;
+mod cron_retry;
 
 use tokio_cron_scheduler::{Job, JobScheduler};
 
-use crate::core::Store;
+use crate::core::{Store, RPC};
 use crate::util::LibResult;
 
 pub async fn run(store: Store) -> LibResult<()> {
@@ -19,16 +21,47 @@ pub async fn run(store: Store) -> LibResult<()> {
         })
     })?;
 
+    let store2 = store.clone();
     let rate_job = Job::new_async("5 0 * * * *", move |_, _| {
-        let stores = store.clone();
+        let stores = store2.clone();
         Box::pin(async move {
             let cron = cron_rate::CronRate::new(stores);
             cron.run().await.expect("TODO: panic message");
         })
     })?;
 
+    let store3 = store.clone();
+    let retry_job = Job::new_async("30 * * * * *", move |_, _| {
+        let stores = store3.clone();
+        Box::pin(async move {
+            let cron = cron_retry::CronRetry::new(stores);
+            if let Err(e) = cron.run().await {
+                tracing::error!("cron retry err={e}")
+            }
+        })
+    })?;
+
+    let balance_job = Job::new_async("15 */30 * * * *", move |_, _| {
+        let stores = store.clone();
+        Box::pin(async move {
+            let cron = cron_balance::CronBalance::new(stores);
+            if let Err(e) = cron.run().await {
+                tracing::error!("cron balance err={e}")
+            }
+        })
+    })?;
+
+    let rpc_job = Job::new_async("*/10 * * * * *", move |_, _| {
+        Box::pin(async move {
+            RPC.check_health().await;
+        })
+    })?;
+
     sched.add(price_job).await?;
     sched.add(rate_job).await?;
+    sched.add(retry_job).await?;
+    sched.add(balance_job).await?;
+    sched.add(rpc_job).await?;
     sched.start().await?;
     Ok(())
 }
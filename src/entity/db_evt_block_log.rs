@@ -0,0 +1,15 @@
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "evt_block_log")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub block_number: i64,
+    pub block_hash: String,
+    pub block_time: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
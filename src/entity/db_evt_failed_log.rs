@@ -0,0 +1,58 @@
+use sea_orm::entity::prelude::*;
+
+/// waiting for the next retry
+pub const STATUS_PENDING: i32 = 0;
+/// handled by a retry
+pub const STATUS_RESOLVED: i32 = 1;
+/// discarded by an operator
+pub const STATUS_DISCARDED: i32 = 2;
+/// retried `MAX_RETRY_ATTEMPTS` times without success
+pub const STATUS_EXHAUSTED: i32 = 3;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "evt_failed_log")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub block_number: i64,
+    pub txn_index: i64,
+    pub log_index: i64,
+    pub block_time: i64,
+    pub txn_hash: String,
+    pub address: String,
+    pub topic_0: String,
+    pub topic_1: Option<String>,
+    pub topic_2: Option<String>,
+    pub topic_3: Option<String>,
+    pub data: Option<String>,
+    pub error: String,
+    pub attempts: i32,
+    pub status: i32,
+    pub next_retry_ts: i64,
+    pub create_ts: i64,
+    pub update_ts: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Model {
+    /// the raw log, as it is handed to the event handlers
+    pub fn txn_model(&self) -> super::db_evt_txn_log::Model {
+        super::db_evt_txn_log::Model {
+            block_number: self.block_number,
+            txn_index: self.txn_index,
+            log_index: self.log_index,
+            block_time: self.block_time,
+            txn_hash: self.txn_hash.clone(),
+            address: self.address.clone(),
+            topic_0: self.topic_0.clone(),
+            topic_1: self.topic_1.clone(),
+            topic_2: self.topic_2.clone(),
+            topic_3: self.topic_3.clone(),
+            data: self.data.clone(),
+        }
+    }
+}
@@ -16,6 +16,8 @@ pub struct Model {
     pub pair_address: String,
     pub token_id: i64,
     pub init_price: Decimal,
+    /// launch price in USD, at the oracle round of the launch
+    pub init_price_usd: Decimal,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -20,7 +20,14 @@ pub struct Model {
     pub token1: String,
     pub amount1: Decimal,
     pub price: Decimal,
-    pub price_token: Decimal
+    pub price_token: Decimal,
+    pub is_final: bool,
+    /// amount1 including the fee, only known for graduation buys
+    pub amount1_with_fee: Option<Decimal>,
+    /// the purchase that pushed the token over the graduation line
+    pub is_grad_buy: bool,
+    /// where the trade happened, `curve` or `uniswap_v2`
+    pub venue: String,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
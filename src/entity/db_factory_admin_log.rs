@@ -0,0 +1,33 @@
+use sea_orm::entity::prelude::*;
+
+/// Role, ownership, initialization and upgrade events of the factory, for security review.
+///
+/// Roles are stored by name when they are one of the factory's role constants, otherwise as hex.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "factory_admin_log")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub block_number: i64,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub txn_index: i64,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub log_index: i64,
+    pub block_time: i64,
+    pub txn_hash: String,
+    /// event name, e.g. `RoleGranted`
+    pub event: String,
+    pub role: Option<String>,
+    /// account a role is granted to or revoked from
+    pub account: Option<String>,
+    /// caller of a role change
+    pub sender: Option<String>,
+    /// previous owner or admin role
+    pub prev_value: Option<String>,
+    /// new owner, admin role, initialized version or implementation
+    pub new_value: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,26 @@
+use sea_orm::entity::prelude::*;
+
+/// A factory parameter and the blocks it was in effect, `from_block <= block < to_block`.
+///
+/// Parameters set per raised asset (launch fee) carry the asset, the others an empty string.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "factory_config")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub param: String,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub asset: String,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub from_block: i64,
+    /// `None` while the value is current
+    pub to_block: Option<i64>,
+    pub value: String,
+    pub block_time: i64,
+    /// empty for values seeded from the view functions
+    pub txn_hash: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,34 @@
+use rust_decimal::Decimal;
+use sea_orm::entity::prelude::*;
+
+/// Candles of every `PeriodType`, `period` holds `PeriodType::name`.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "kline")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub token_address: String,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub period: String,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub open_ts: i64,
+    pub close_ts: i64,
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+    pub volume: Decimal,
+    pub amount: Decimal,
+    pub txn_num: i64,
+    /// USD prices of the trades, from `evt_trade_log.price`
+    pub open_usd: Decimal,
+    pub high_usd: Decimal,
+    pub low_usd: Decimal,
+    pub close_usd: Decimal,
+    /// USD volume
+    pub amount_usd: Decimal,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
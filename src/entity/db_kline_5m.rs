@@ -16,6 +16,13 @@ pub struct Model {
     pub volume: Decimal,
     pub amount: Decimal,
     pub txn_num: i64,
+    /// USD prices of the trades, from `evt_trade_log.price`
+    pub open_usd: Decimal,
+    pub high_usd: Decimal,
+    pub low_usd: Decimal,
+    pub close_usd: Decimal,
+    /// USD volume
+    pub amount_usd: Decimal,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -0,0 +1,25 @@
+use rust_decimal::Decimal;
+use sea_orm::entity::prelude::*;
+
+/// Rounds of the raised tokens' oracles, to price trades at the round active at their block time.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "oracle_price_log")]
+pub struct Model {
+    /// oracle proxy, as in `raised_token.oracle`
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub oracle: String,
+    /// aggregator of the proxy phase that produced the round
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub aggregator: String,
+    /// round of the aggregator, not of the proxy
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub round_id: i64,
+    /// USD price, decimals of the feed applied
+    pub answer: Decimal,
+    pub updated_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
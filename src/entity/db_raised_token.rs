@@ -10,9 +10,16 @@ pub struct Model {
     pub symbol: String,
     pub decimal: i32,
     pub icon: String,
+    /// price feed, set by the operator, empty until then
     pub oracle: String,
     pub price: Decimal,
     pub create_ts: i64,
+    pub is_whitelisted: bool,
+    /// aggregator behind `oracle`, the emitter of `AnswerUpdated`
+    pub aggregator: String,
+    /// aggregator round and update time of `price`
+    pub price_round: i64,
+    pub price_ts: i64,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
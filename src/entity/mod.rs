@@ -1,17 +1,29 @@
+#[allow(dead_code)]
 pub mod db_user;
+#[allow(dead_code)]
 pub mod db_user_avatar;
+#[allow(dead_code)]
 pub mod db_tag_info;
 pub mod db_evt_trade_log;
 pub mod db_token_info;
 pub mod db_raised_token;
+#[allow(dead_code)]
 pub mod db_token_comment;
 pub mod db_token_summary;
 pub mod db_evt_token_log;
 pub mod db_evt_balance_log;
 pub mod db_user_summary;
 pub mod db_kline_5m;
+pub mod db_kline;
 pub mod db_evt_transfer_log;
 pub mod db_evt_txn_log;
+pub mod db_evt_block_log;
+pub mod db_indexer_cursor;
+pub mod db_indexer_state;
+pub mod db_evt_failed_log;
+pub mod db_factory_config;
+pub mod db_factory_admin_log;
+pub mod db_oracle_price_log;
 
 // pub use user::Entity as User;
 // pub use user_avatar::Entity as UserAvatar;
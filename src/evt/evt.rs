@@ -1,53 +1,102 @@
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
 use std::str::FromStr;
-use std::time::Duration;
+use std::sync::atomic::AtomicU64;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
-use super::evt_trade::handle_trade;
-use crate::core::{consts, Store};
+use super::evt_admin::{handle_admin, AdminAlerts};
+use super::evt_config::handle_config;
+use super::evt_failed::save_failed;
+use super::evt_oracle::{handle_answer_updated, price_at, sync_prices};
+use super::evt_pair::{handle_sync, sync_pair_summary};
+use super::evt_reorg::rollback;
+use super::evt_trade::{handle_trade, is_handled};
+use super::evt_transfer::handle_transfer;
+use super::evt_whitelist::handle_whitelist;
+use crate::core::{consts, Store, RPC};
 use crate::entity::*;
-use crate::svc::TOKEN;
+use crate::svc::{OraclePrice, PRICE, TOKEN};
 use crate::util::{LibError, LibResult, PeriodType};
-use alloy::eips::BlockId;
+use alloy::eips::{BlockId, BlockNumberOrTag};
 use alloy::network::Ethereum;
 use alloy::primitives::utils::format_ether;
-use alloy::primitives::Address;
+use alloy::primitives::{Address, Bytes, B256};
 use alloy::providers::{Provider, RootProvider};
-use alloy::rpc::types::{Filter, RawLog};
+use alloy::rpc::types::{Filter, Log, RawLog};
 use alloy::sol_types::SolEvent;
+use lru::LruCache;
 use redis::AsyncCommands;
 use rust_decimal::Decimal;
 use sea_orm::prelude::Expr;
 use sea_orm::sea_query::OnConflict;
 use sea_orm::ActiveValue::Set;
 use sea_orm::{
-    ActiveModelTrait, ColumnTrait, EntityTrait, IntoActiveModel, QueryFilter, QuerySelect,
-    TransactionTrait,
+    ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, IntoActiveModel, Iterable,
+    QueryFilter, QueryOrder, TransactionTrait,
 };
+use tokio::sync::OnceCell;
 
 pub struct Evt {
-    store: Store,
-    provider: RootProvider<Ethereum>,
-    factory_contract: Address,
+    pub(super) store: Store,
+    pub(super) provider: RootProvider<Ethereum>,
+    pub(super) factory_contract: Address,
+    chain_id: OnceCell<u64>,
+    pub(super) block_cache: Mutex<LruCache<u64, db_evt_block_log::Model>>,
+    /// blocks per `get_logs` request, adapted to the limits of the provider
+    pub(super) log_range: AtomicU64,
+}
+
+/// Effects of handled logs outside the database, they may only happen once the transaction
+/// of the logs is committed.
+#[derive(Default)]
+#[must_use]
+pub(super) struct AfterCommit {
+    pub(super) alerts: AdminAlerts,
+    /// new oracle prices for the price cache
+    pub(super) prices: Vec<OraclePrice>,
+}
+
+impl AfterCommit {
+    fn extend(&mut self, other: AfterCommit) {
+        self.alerts.extend(other.alerts);
+        self.prices.extend(other.prices);
+    }
+
+    pub(super) fn apply(self) {
+        for price in self.prices {
+            PRICE.set(price);
+        }
+        self.alerts.send();
+    }
 }
 
 impl Evt {
     pub fn new(store: Store) -> Self {
-        let url = consts::PROVIDER
-            .parse()
-            .expect("Could not parse the Provider URL");
         let factory_contract = consts::FACTORY_CONTRACT_ADDR
             .as_str()
             .parse()
             .expect("Could not parse the Factory Contract Address");
-        let provider = RootProvider::new_http(url);
+        let provider = RPC.provider();
 
         Self {
             store,
             provider,
             factory_contract,
+            chain_id: OnceCell::new(),
+            block_cache: Mutex::new(LruCache::new(
+                NonZeroUsize::new(consts::BLOCK_CACHE_SIZE).unwrap(),
+            )),
+            log_range: AtomicU64::new(consts::MAX_BLOCK_RANGE),
         }
     }
 
     pub async fn run(&self) -> LibResult<()> {
+        // fees of the indexed trades depend on it, do not start without
+        self.seed_config().await?;
+        if let Err(e) = sync_prices(&self.store.db_pool).await {
+            tracing::error!("sync raised token prices err={e}");
+        }
         let start_block = self.get_block().await?;
         let (mut start_block, mut latest_block) = self.block_range(start_block).await;
         // catch up block
@@ -66,51 +115,114 @@ impl Evt {
             }
         }
         // start filter
-        self.run_filter(start_block, latest_block).await?;
+        if consts::WS_PROVIDER.is_some() {
+            self.run_subscribe(start_block - 1).await?;
+        } else {
+            self.run_filter(start_block, latest_block, None).await?;
+        }
         Ok(())
     }
 
-    async fn run_filter(&self, mut start_block: u64, mut latest_block: u64) -> LibResult<()> {
+    /// Poll new blocks until `deadline`, returns the last indexed block.
+    pub(super) async fn run_filter(
+        &self,
+        mut start_block: u64,
+        mut latest_block: u64,
+        deadline: Option<Instant>,
+    ) -> LibResult<u64> {
         loop {
             if let Err(e) = self.filter(start_block, latest_block).await {
                 tracing::error!("filter err: {}", e);
                 continue;
             }
+            if deadline.is_some_and(|v| Instant::now() >= v) {
+                return Ok(latest_block);
+            }
             (start_block, latest_block) = self.block_range(latest_block).await;
         }
     }
 
-    async fn filter(&self, start_block: u64, latest_block: u64) -> LibResult<()> {
+    /// Index the logs of `[start_block, latest_block]`.
+    ///
+    /// All rows of the range, a possible reorg rollback and the cursor are written in one
+    /// transaction, every log is handled in its own savepoint so a failing event does not
+    /// abort the range.
+    pub(super) async fn filter(&self, start_block: u64, latest_block: u64) -> LibResult<()> {
+        let tx = self.store.db_pool.begin().await?;
+        let start_block = match self.check_reorg(&tx, start_block).await? {
+            Some(fork_block) => fork_block,
+            None => start_block,
+        };
         tracing::info!("start block: {start_block}, end block: {latest_block}");
         let addrs = vec![self.factory_contract];
 
         let filter = Filter::new()
             .address(addrs)
-            .from_block(start_block)
-            .to_block(latest_block)
             .events(vec![
-                // "Transfer(address,address,uint256)",
                 "Launched(address,address,address,uint256,uint256,uint256)",
                 "InitialBuyAndUpdate(address,address,uint256,uint256,uint256)",
                 "Sold(address,address,uint256,uint256,uint256)",
                 "Bought(address,address,uint256,uint256,uint256)",
                 "Graduated(address,address)",
+                "PurchaseToGrad(address,address,address,uint256,uint256,uint256)",
+                "FeeParametersSet(address,uint256,uint256)",
+                "LaunchFeeSet(address,uint256)",
+                "LaunchPointPercentageSet(uint256)",
+                "MigrationTaxSet(uint64)",
+                "InitialSupplySet(uint256)",
+                "InitialYSet(uint256)",
+                "MaxFunCurveSet(address)",
+                "MaxFunManagerSet(address)",
+                "SignerSet(address)",
+                "AssetAddedToWhitelist(address)",
+                "AssetRemovedFromWhitelist(address)",
+                "RoleGranted(bytes32,address,address)",
+                "RoleRevoked(bytes32,address,address)",
+                "RoleAdminChanged(bytes32,bytes32,bytes32)",
+                "OwnershipTransferStarted(address,address)",
+                "OwnershipTransferred(address,address)",
+                "Initialized(uint64)",
+                "Upgraded(address)",
             ]);
-        let logs = match self.provider.get_logs(&filter).await {
-            Ok(v) => v,
-            Err(e) => {
-                tracing::error!("get logs err={e}");
-                return Err(LibError::AlloyEthersError(e));
-            }
-        };
+        let mut logs = self.get_logs(&filter, start_block, latest_block).await?;
+        let transfer_logs = self
+            .transfer_logs(&tx, &logs, start_block, latest_block)
+            .await?;
+        let pair_logs = self
+            .pair_logs(&tx, &logs, start_block, latest_block)
+            .await?;
+        let oracle_logs = self.oracle_logs(start_block, latest_block).await?;
+        logs.extend(transfer_logs);
+        logs.extend(pair_logs);
+        logs.extend(oracle_logs);
+        logs.sort_by_key(|v| (v.block_number, v.log_index));
 
+        let blocks = self.blocks(&logs, latest_block).await?;
+        let after_commit = self.handle_logs(&tx, &logs, &blocks).await?;
+        self.save_blocks(&tx, blocks.into_values().collect(), latest_block)
+            .await?;
+        self.set_block(&tx, latest_block).await?;
+        tx.commit().await?;
+        after_commit.apply();
+        self.set_final(latest_block).await?;
+        Ok(())
+    }
+
+    /// Handle `logs` in chain order, every log in its own savepoint of `tx`.
+    ///
+    /// A log whose handler fails is saved to evt_failed_log instead of aborting the range.
+    /// The returned alerts are to be applied once `tx` is committed.
+    pub(super) async fn handle_logs(
+        &self,
+        tx: &DatabaseTransaction,
+        logs: &[Log],
+        blocks: &HashMap<u64, db_evt_block_log::Model>,
+    ) -> LibResult<AfterCommit> {
+        let senders = self.swap_senders(logs).await?;
+        let mut after_commit = AfterCommit::default();
         for log in logs.iter() {
             let block_number = log.block_number.unwrap();
-            let block_info = self
-                .provider
-                .get_block(BlockId::from(block_number), Default::default())
-                .await?;
-            let block_time = block_info.unwrap().header.timestamp as i64;
+            let block_time = blocks[&block_number].block_time;
             let topic = format!("{:#x}", log.topic0().unwrap());
             let txn_hash = format!("{:#x}", log.transaction_hash.unwrap());
             let txn_model = db_evt_txn_log::Model {
@@ -127,59 +239,230 @@ impl Evt {
                 data: Some(format!("{:#x}", log.data().data)),
             };
 
-            let raw_log = RawLog {
-                address: log.address(),
-                topics: log.topics().to_vec(),
-                data: log.data().clone().data,
-            };
-            match topic.as_str() {
-                "0x46763160257a346e655d6a803f4d4b1b91bfa36e12a402b37f5e40aa71945e84" => {
-                    // launched
-                    if let Err(e) = self.handle_launched_evt(raw_log, txn_model).await {
-                        tracing::error!("handle_launched_evt. txn_hash={txn_hash}, err={e}")
-                    }
-                }
-                "0x1685b8781b8be9c9242e31a14f2ca289c99bf831d0ad45bf23613f6e646e480d" => {
-                    // initial buy and update
-                    if let Err(e) = self
-                        .handle_initial_buy_and_update_evt(raw_log, txn_model)
-                        .await
-                    {
-                        tracing::error!(
-                            "handle_initial_buy_and_update_evt. txn_hash={txn_hash}, err={e}"
-                        )
-                    }
+            let sp = tx.begin().await?;
+            let mut log_after_commit = AfterCommit::default();
+            match self
+                .handle_log(&sp, txn_model.clone(), &senders, &mut log_after_commit)
+                .await
+            {
+                Ok(_) => {
+                    sp.commit().await?;
+                    after_commit.extend(log_after_commit);
                 }
-                "0x9be8a5ca22b7e6e81f04b5879f0248227bb770114291bd47dfaee4c3a82ad60e" => {
-                    // sold
-                    if let Err(e) = self.handle_sold_evt(raw_log, txn_model).await {
-                        tracing::error!("handle_sold_evt. txn_hash={txn_hash}, err={e}")
-                    }
-                }
-                "0x7ce543d1780f3bdc3dac42da06c95da802653cd1b212b8d74ec3e3c33ad7095c" => {
-                    // bought
-                    if let Err(e) = self.handle_bought_evt(raw_log, txn_model).await {
-                        tracing::error!("handle_bought_evt. txn_hash={txn_hash}, err={e}")
-                    }
-                }
-                "0x381d54fa425631e6266af114239150fae1d5db67bb65b4fa9ecc65013107e07e" => {
-                    // graduated
-                    if let Err(e) = self.handle_graduated_evt(raw_log, txn_model).await {
-                        tracing::error!("handle_graduated_evt. txn_hash={txn_hash}, err={e}")
-                    }
+                Err(e) => {
+                    tracing::error!("handle evt. topic={topic}, txn_hash={txn_hash}, err={e}");
+                    sp.rollback().await?;
+                    save_failed(tx, &txn_model, &e).await?;
                 }
-                _ => tracing::warn!("unknown evt {txn_model:#?}"),
             }
         }
-        self.set_block(latest_block).await?;
+        Ok(after_commit)
+    }
+
+    /// decode the raw log of `txn_model` and dispatch it to the handler of its event
+    ///
+    /// `senders` are the prefetched senders of swap transactions, see `swap_senders`.
+    pub(super) async fn handle_log(
+        &self,
+        tx: &DatabaseTransaction,
+        txn_model: db_evt_txn_log::Model,
+        senders: &HashMap<B256, Address>,
+        after_commit: &mut AfterCommit,
+    ) -> LibResult<()> {
+        let topics = [
+            Some(&txn_model.topic_0),
+            txn_model.topic_1.as_ref(),
+            txn_model.topic_2.as_ref(),
+            txn_model.topic_3.as_ref(),
+        ];
+        let raw_log = RawLog {
+            address: txn_model.address.parse()?,
+            topics: topics
+                .into_iter()
+                .flatten()
+                .map(|v| B256::from_str(v))
+                .collect::<Result<_, _>>()?,
+            data: Bytes::from_str(txn_model.data.as_deref().unwrap_or_default())?,
+        };
+        match txn_model.topic_0.as_str() {
+            "0x46763160257a346e655d6a803f4d4b1b91bfa36e12a402b37f5e40aa71945e84" => {
+                // launched
+                self.handle_launched_evt(tx, raw_log, txn_model).await
+            }
+            "0x1685b8781b8be9c9242e31a14f2ca289c99bf831d0ad45bf23613f6e646e480d" => {
+                // initial buy and update
+                self.handle_initial_buy_and_update_evt(tx, raw_log, txn_model)
+                    .await
+            }
+            "0x9be8a5ca22b7e6e81f04b5879f0248227bb770114291bd47dfaee4c3a82ad60e" => {
+                // sold
+                self.handle_sold_evt(tx, raw_log, txn_model).await
+            }
+            "0x7ce543d1780f3bdc3dac42da06c95da802653cd1b212b8d74ec3e3c33ad7095c" => {
+                // bought
+                self.handle_bought_evt(tx, raw_log, txn_model).await
+            }
+            "0x381d54fa425631e6266af114239150fae1d5db67bb65b4fa9ecc65013107e07e" => {
+                // graduated
+                self.handle_graduated_evt(tx, raw_log, txn_model).await
+            }
+            "0x7d4f1f5ddf32acdda6a05ae602c10dc4dfb2cb3d30a5a8a2375a654f30545e47" => {
+                // purchase to graduation
+                self.handle_purchase_to_grad_evt(tx, raw_log, txn_model)
+                    .await
+            }
+            "0x7d962c7d834145ed7f6a6da552ef8780add580dcd2b0f14a7d648a9cdbe523af" // fee parameters set
+            | "0x93da3acc21c512b09d40df512475464b1d098d348990aa598a1d07e5159d13d6" // launch fee set
+            | "0xefc351b7823bf89d137b5f4c51348788eb887b9884431725ee0dc798bb54d819" // launch point percentage set
+            | "0xb2f467bf212c8871e24794948c2ab9dd3dadd155c16006dbc993c169c40a8f46" // migration tax set
+            | "0x9f3cb42384148de58b723104e79108cdd974b2185b03421f201530f9cefb1fda" // initial supply set
+            | "0x06743e4e67e7043c40885fdafbd88b829ffd4dd93b7f26ac156de3a9e7d868d9" // initial y set
+            | "0xc18d7fadfb4dc6d05dfd4b19d60b754ab571b650495cd7b04da2687b211a32c4" // max fun curve set
+            | "0xe850fa38273d14d9fb681878507d9c5c13505015c3f6d6bccc15df72cd24eb55" // max fun manager set
+            | "0x9eaa897564d022fb8c5efaf0acdb5d9d27b440b2aad44400b6e1c702e65b9ed3" // signer set
+            => handle_config(tx, raw_log, txn_model).await,
+            "0x7012f2dd489a23d70c7a9ff355b2604e3055cd1298934109b8f513ce7fa00055" // asset added to whitelist
+            | "0x9d64110ab84413b321236d9ae67b494d51691ffa38bc59d9d858143f270d3432" // asset removed from whitelist
+            => handle_whitelist(tx, raw_log, txn_model).await,
+            "0x2f8788117e7eff1d82e926ec794901d17c78024a50270940304540a733656f0d" // role granted
+            | "0xf6391f5c32d9c69d2a47ea670b442974b53935d1edc7fd64eb21e047a839171b" // role revoked
+            | "0xbd79b86ffe0ab8e8776151514217cd7cacd52c909f66475c3af44e129f0b00ff" // role admin changed
+            | "0x38d16b8cac22d99fc7c124b9cd0de2d3fa1faef420bfe791d8c362d765e22700" // ownership transfer started
+            | "0x8be0079c531659141344cd1fd0a4f28419497f9722a3daafe3b4186f6b6457e0" // ownership transferred
+            | "0xc7f505b2f371ae2175ee4913f4499e1f2633a7b5936321eed1cdaeb6115181d2" // initialized
+            | "0xbc7cd75a20ee27fd9adebab32041f755214dbc6bffa90cc0225b39da2e5c2d3b" // upgraded
+            => handle_admin(tx, raw_log, txn_model, &mut after_commit.alerts).await,
+            "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef" => {
+                // transfer
+                handle_transfer(tx, raw_log, txn_model).await
+            }
+            "0xd78ad95fa46c994b6551d0da85fc275fe613ce37657fb8d5e3d130840159d822" => {
+                // uniswap v2 swap
+                self.handle_swap(tx, raw_log, txn_model, senders).await
+            }
+            "0x1c411e9a96e071241c2f21f7726b17ae89e3cab4c78be50e062b03a9fffbbad1" => {
+                // uniswap v2 sync
+                handle_sync(tx, raw_log, txn_model).await
+            }
+            "0x0559884fd3a460db3073b7fc896cc77986f16e378210ded43186175bf646fc5f" => {
+                // oracle answer updated
+                handle_answer_updated(tx, raw_log, txn_model, &mut after_commit.prices).await
+            }
+            _ => {
+                tracing::warn!("unknown evt {txn_model:#?}");
+                Ok(())
+            }
+        }
+    }
+
+    pub(super) async fn block_model(
+        &self,
+        block_number: u64,
+    ) -> LibResult<db_evt_block_log::Model> {
+        let block = self
+            .provider
+            .get_block(BlockId::from(block_number), Default::default())
+            .await?
+            .ok_or_else(|| LibError::InternalError(format!("block {block_number} not found")))?;
+        Ok(db_evt_block_log::Model {
+            block_number: block_number as i64,
+            block_hash: format!("{:#x}", block.header.hash),
+            block_time: block.header.timestamp as i64,
+        })
+    }
+
+    /// record the hashes of processed blocks, only the last `REORG_DEPTH` blocks are kept
+    async fn save_blocks(
+        &self,
+        tx: &DatabaseTransaction,
+        blocks: Vec<db_evt_block_log::Model>,
+        latest_block: u64,
+    ) -> LibResult<()> {
+        let onconflict = OnConflict::column(db_evt_block_log::Column::BlockNumber)
+            .update_column(db_evt_block_log::Column::BlockHash)
+            .update_column(db_evt_block_log::Column::BlockTime)
+            .to_owned();
+        db_evt_block_log::Entity::insert_many(blocks.into_iter().map(|v| v.into_active_model()))
+            .on_conflict(onconflict)
+            .exec(tx)
+            .await?;
+        db_evt_block_log::Entity::delete_many()
+            .filter(
+                db_evt_block_log::Column::BlockNumber
+                    .lt(latest_block.saturating_sub(consts::REORG_DEPTH) as i64),
+            )
+            .exec(tx)
+            .await?;
         Ok(())
     }
 
-    async fn block_range(&self, latest_block: u64) -> (u64, u64) {
+    /// Check whether the chain still contains the last indexed block.
+    ///
+    /// The parent hash of `start_block` is compared with the recorded hash of the last
+    /// indexed block. On mismatch the recorded blocks are walked back until one is still
+    /// canonical, everything written above it is rolled back and the fork block is returned
+    /// so indexing restarts from there.
+    async fn check_reorg(
+        &self,
+        tx: &DatabaseTransaction,
+        start_block: u64,
+    ) -> LibResult<Option<u64>> {
+        let records = db_evt_block_log::Entity::find()
+            .filter(db_evt_block_log::Column::BlockNumber.lt(start_block as i64))
+            .order_by_desc(db_evt_block_log::Column::BlockNumber)
+            .all(tx)
+            .await?;
+        let Some(last) = records.first() else {
+            return Ok(None);
+        };
+        let canonical = if last.block_number as u64 + 1 == start_block {
+            let block = self
+                .provider
+                .get_block(BlockId::from(start_block), Default::default())
+                .await?
+                .ok_or_else(|| LibError::InternalError(format!("block {start_block} not found")))?;
+            format!("{:#x}", block.header.parent_hash) == last.block_hash
+        } else {
+            self.block_model(last.block_number as u64).await?.block_hash == last.block_hash
+        };
+        if canonical {
+            return Ok(None);
+        }
+
+        let mut fork_block = records.last().unwrap().block_number as u64;
+        let mut found = false;
+        for record in records.iter().skip(1) {
+            let block = self.block_model(record.block_number as u64).await?;
+            if block.block_hash == record.block_hash {
+                fork_block = record.block_number as u64 + 1;
+                found = true;
+                break;
+            }
+        }
+        if !found {
+            tracing::error!(
+                "reorg deeper than {} blocks, roll back from oldest recorded block {fork_block}",
+                consts::REORG_DEPTH
+            );
+        }
+        tracing::warn!(
+            "chain reorg detected. last indexed block: {}, fork block: {fork_block}",
+            last.block_number
+        );
+        if fork_block <= self.confirmed_block().await? {
+            tracing::error!("reorg below the confirmed block, fork block: {fork_block}");
+        }
+        self.clear_block_cache();
+        rollback(tx, fork_block).await?;
+        self.set_block(tx, fork_block - 1).await?;
+        Ok(Some(fork_block))
+    }
+
+    pub(super) async fn block_range(&self, latest_block: u64) -> (u64, u64) {
         let mut new_num;
         loop {
             tokio::time::sleep(Duration::new(consts::POLL_INTERVAL, 0)).await;
-            new_num = match self.provider.get_block_number().await {
+            new_num = match self.head_block().await {
                 Ok(v) => v,
                 Err(e) => {
                     tracing::error!("get block number err={e}");
@@ -196,80 +479,162 @@ impl Evt {
         (latest_block + 1, new_num)
     }
 
-    async fn get_block(&self) -> LibResult<u64> {
+    /// the block the indexer scans up to
+    pub(super) async fn head_block(&self) -> LibResult<u64> {
+        if *consts::INDEX_UNCONFIRMED {
+            Ok(self.provider.get_block_number().await?)
+        } else {
+            self.confirmed_block().await
+        }
+    }
+
+    /// the newest block that is `CONFIRM_DEPTH` blocks behind `CONFIRM_HEAD`
+    async fn confirmed_block(&self) -> LibResult<u64> {
+        let head = match *consts::CONFIRM_HEAD {
+            BlockNumberOrTag::Latest => self.provider.get_block_number().await?,
+            tag => {
+                self.provider
+                    .get_block(BlockId::Number(tag), Default::default())
+                    .await?
+                    .ok_or_else(|| LibError::InternalError(format!("{tag} block not found")))?
+                    .header
+                    .number
+            }
+        };
+        Ok(head.saturating_sub(*consts::CONFIRM_DEPTH))
+    }
+
+    /// mark the trades up to the confirmed block as final and publish the block for frontends
+    async fn set_final(&self, latest_block: u64) -> LibResult<()> {
+        let final_block = if *consts::INDEX_UNCONFIRMED {
+            self.confirmed_block().await?.min(latest_block)
+        } else {
+            latest_block
+        };
+        db_evt_trade_log::Entity::update_many()
+            .filter(db_evt_trade_log::Column::IsFinal.eq(false))
+            .filter(db_evt_trade_log::Column::BlockNumber.lte(final_block as i64))
+            .col_expr(db_evt_trade_log::Column::IsFinal, Expr::value(true))
+            .exec(&self.store.db_pool)
+            .await?;
+
         let mut conn = self
             .store
             .redis_pool
             .get_multiplexed_async_connection()
             .await?;
-        let re: Option<u64> = conn.get(consts::PK_BLOCK_NUM).await?;
-        match re {
-            Some(v) => Ok(v),
-            None => {
-                self.set_block(*consts::INIT_BLOCK).await?;
-                Ok(*consts::INIT_BLOCK)
-            }
-        }
+        conn.set::<_, _, ()>(consts::PK_FINAL_BLOCK_NUM, final_block)
+            .await?;
+        Ok(())
     }
 
-    async fn set_block(&self, block: u64) -> LibResult<()> {
+    pub(super) async fn chain_id(&self) -> LibResult<i64> {
+        let chain_id = self
+            .chain_id
+            .get_or_try_init(|| self.provider.get_chain_id())
+            .await?;
+        Ok(*chain_id as i64)
+    }
+
+    /// The indexed block cursor, `None` before the first range is indexed.
+    pub(super) async fn cursor_block(&self) -> LibResult<Option<u64>> {
+        let contract = format!("{:#x}", self.factory_contract);
+        let chain_id = self.chain_id().await?;
+        let cursor = db_indexer_cursor::Entity::find_by_id((contract, chain_id))
+            .one(&self.store.db_pool)
+            .await?;
+        Ok(cursor.map(|v| v.block_number as u64))
+    }
+
+    /// Read the indexed block cursor.
+    ///
+    /// The cursor used to live in the redis key `PK_BLOCK_NUM`, if there is no cursor row
+    /// yet it is taken over from there once, otherwise indexing starts at `INIT_BLOCK`.
+    pub(super) async fn get_block(&self) -> LibResult<u64> {
+        if let Some(block) = self.cursor_block().await? {
+            return Ok(block);
+        }
+
         let mut conn = self
             .store
             .redis_pool
             .get_multiplexed_async_connection()
             .await?;
-        conn.set::<_, _, ()>(consts::PK_BLOCK_NUM, block).await?;
+        let re: Option<u64> = conn.get(consts::PK_BLOCK_NUM).await?;
+        let block = match re {
+            Some(v) => {
+                tracing::info!("migrate block cursor from redis. block: {v}");
+                v
+            }
+            None => *consts::INIT_BLOCK,
+        };
+        let tx = self.store.db_pool.begin().await?;
+        self.set_block(&tx, block).await?;
+        tx.commit().await?;
+        Ok(block)
+    }
+
+    async fn set_block(&self, tx: &DatabaseTransaction, block: u64) -> LibResult<()> {
+        let cursor = db_indexer_cursor::ActiveModel {
+            contract: Set(format!("{:#x}", self.factory_contract)),
+            chain_id: Set(self.chain_id().await?),
+            block_number: Set(block as i64),
+            update_ts: Set(chrono::Utc::now().timestamp()),
+        };
+        let onconflict = OnConflict::columns([
+            db_indexer_cursor::Column::Contract,
+            db_indexer_cursor::Column::ChainId,
+        ])
+        .update_column(db_indexer_cursor::Column::BlockNumber)
+        .update_column(db_indexer_cursor::Column::UpdateTs)
+        .to_owned();
+        db_indexer_cursor::Entity::insert(cursor)
+            .on_conflict(onconflict)
+            .exec(tx)
+            .await?;
         Ok(())
     }
 
     async fn handle_launched_evt(
         &self,
+        tx: &DatabaseTransaction,
         raw_log: RawLog,
         txn_model: db_evt_txn_log::Model,
     ) -> LibResult<()> {
+        if is_handled(tx, &txn_model).await? {
+            return Ok(());
+        }
         let data =
             consts::FACTORY::Launched::decode_raw_log(raw_log.topics, raw_log.data.as_ref(), true)?;
         let token = format!("{:#x}", data.token);
         let asset = format!("{:#x}", data.asset);
         let pair = format!("{:#x}", data.pair);
         let id = data.id.to::<i64>();
-        let total_supply = TOKEN.total_supply(&token).await?;
-
-        let oracle_address = db_raised_token::Entity::find()
-            .filter(db_raised_token::Column::Address.eq(&asset))
-            .select_only()
-            .column(db_raised_token::Column::Oracle)
-            .into_tuple::<String>()
-            .one(&self.store.db_pool)
-            .await?
-            .ok_or_else(|| LibError::InternalError("asset info not found".to_string()))?;
+        let block = Some(BlockId::number(txn_model.block_number as u64));
+        let total_supply = TOKEN.total_supply(&token, block).await?;
 
         let price_value = Decimal::from_str(&format_ether(data.initialPrice))?;
-        let oracle_price = TOKEN.oracle_price(&oracle_address).await?;
+        let oracle_price = price_at(
+            tx,
+            &asset,
+            txn_model.block_time,
+            txn_model.block_number as u64,
+        )
+        .await?;
         let price_usd = price_value * oracle_price;
 
-        let token_info = db_token_info::Entity::find_by_id(id as i32)
-            .one(&self.store.db_pool)
+        db_token_info::Entity::find_by_id(id as i32)
+            .one(tx)
             .await?
             .ok_or_else(|| LibError::InternalError("token info not found".to_string()))?;
 
-        let user_balance = TOKEN.balance_of(&token, &token_info.user_address).await?;
-
-        let user_onconflict = OnConflict::columns([
-            db_user_summary::Column::UserAddress,
-            db_user_summary::Column::TokenAddress,
-        ])
-        .update_column(db_user_summary::Column::Amount)
-        .update_column(db_user_summary::Column::UpdateTs)
-        .to_owned();
-
         // 1. update token_info
         // 2. insert txn_log
         // 3. insert evt_token_log
         let token_log_model = db_evt_token_log::ActiveModel {
             block_number: Set(txn_model.block_number),
             txn_index: Set(txn_model.txn_index),
-            log_index: Set(txn_model.txn_index),
+            log_index: Set(txn_model.log_index),
             block_time: Set(txn_model.block_time),
             txn_hash: Set(txn_model.txn_hash.clone()),
             token_address: Set(token.clone()),
@@ -277,6 +642,7 @@ impl Evt {
             pair_address: Set(pair.clone()),
             token_id: Set(id),
             init_price: Set(price_value),
+            init_price_usd: Set(price_usd),
         };
         let token_summary_model = db_token_summary::ActiveModel {
             token_address: Set(token.clone()),
@@ -307,8 +673,12 @@ impl Evt {
             volume: Set(Decimal::ZERO),
             amount: Set(Decimal::ZERO),
             txn_num: Set(0),
+            open_usd: Set(price_usd),
+            high_usd: Set(price_usd),
+            low_usd: Set(price_usd),
+            close_usd: Set(price_usd),
+            amount_usd: Set(Decimal::ZERO),
         };
-        let tx = self.store.db_pool.begin().await?;
         db_token_info::Entity::update_many()
             .filter(db_token_info::Column::Id.eq(id))
             .col_expr(db_token_info::Column::TokenAddress, Expr::value(token.clone()))
@@ -316,31 +686,41 @@ impl Evt {
                 db_token_info::Column::LaunchTs,
                 Expr::value(txn_model.block_time),
             )
-            .exec(&tx)
+            .exec(tx)
             .await?;
-        token_log_model.insert(&tx).await?;
-        token_summary_model.insert(&tx).await?;
-        kline_model.insert(&tx).await?;
-        if user_balance != Decimal::ZERO {
-            let user_summary_model = db_user_summary::ActiveModel {
-                user_address: Set(token_info.user_address.clone()),
+        token_log_model.insert(tx).await?;
+        token_summary_model.insert(tx).await?;
+        kline_model.insert(tx).await?;
+        for period in PeriodType::iter() {
+            let open_ts = period.open_ts(txn_model.block_time);
+            let kline_model = db_kline::ActiveModel {
                 token_address: Set(token.clone()),
-                amount: Set(user_balance),
-                update_ts: Set(txn_model.block_time),
+                period: Set(period.name().to_string()),
+                open_ts: Set(open_ts),
+                close_ts: Set(period.close_ts(open_ts)),
+                high: Set(price_value),
+                low: Set(price_value),
+                open: Set(price_value),
+                close: Set(price_value),
+                volume: Set(Decimal::ZERO),
+                amount: Set(Decimal::ZERO),
+                txn_num: Set(0),
+                open_usd: Set(price_usd),
+                high_usd: Set(price_usd),
+                low_usd: Set(price_usd),
+                close_usd: Set(price_usd),
+                amount_usd: Set(Decimal::ZERO),
             };
-            db_user_summary::Entity::insert(user_summary_model)
-                .on_conflict(user_onconflict)
-                .exec(&tx)
-                .await?;
+            kline_model.insert(tx).await?;
         }
-        txn_model.into_active_model().insert(&tx).await?;
-        tx.commit().await?;
+        txn_model.into_active_model().insert(tx).await?;
 
         Ok(())
     }
 
     async fn handle_initial_buy_and_update_evt(
         &self,
+        tx: &DatabaseTransaction,
         raw_log: RawLog,
         txn_model: db_evt_txn_log::Model,
     ) -> LibResult<()> {
@@ -351,13 +731,14 @@ impl Evt {
         )?;
         let (user, token) = (format!("{:#x}", data.user), format!("{:#x}", data.token));
         handle_trade(
-            &self.store,
+            tx,
             user,
             token,
             data.amountIn,
             data.amountOut,
             data.price,
             0,
+            false,
             txn_model,
         )
         .await?;
@@ -367,6 +748,7 @@ impl Evt {
 
     async fn handle_bought_evt(
         &self,
+        tx: &DatabaseTransaction,
         raw_log: RawLog,
         txn_model: db_evt_txn_log::Model,
     ) -> LibResult<()> {
@@ -375,13 +757,14 @@ impl Evt {
 
         let (user, token) = (format!("{:#x}", data.user), format!("{:#x}", data.token));
         handle_trade(
-            &self.store,
+            tx,
             user,
             token,
             data.amountIn,
             data.amountOut,
             data.price,
             0,
+            false,
             txn_model,
         )
         .await?;
@@ -390,6 +773,7 @@ impl Evt {
 
     async fn handle_sold_evt(
         &self,
+        tx: &DatabaseTransaction,
         raw_log: RawLog,
         txn_model: db_evt_txn_log::Model,
     ) -> LibResult<()> {
@@ -398,13 +782,42 @@ impl Evt {
 
         let (user, token) = (format!("{:#x}", data.user), format!("{:#x}", data.token));
         handle_trade(
-            &self.store,
+            tx,
             user,
             token,
             data.amountIn,
             data.amountOut,
             data.price,
             1,
+            false,
+            txn_model,
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn handle_purchase_to_grad_evt(
+        &self,
+        tx: &DatabaseTransaction,
+        raw_log: RawLog,
+        txn_model: db_evt_txn_log::Model,
+    ) -> LibResult<()> {
+        let data = consts::FACTORY::PurchaseToGrad::decode_raw_log(
+            raw_log.topics,
+            raw_log.data.as_ref(),
+            true,
+        )?;
+
+        let (user, token) = (format!("{:#x}", data.user), format!("{:#x}", data.token));
+        handle_trade(
+            tx,
+            user,
+            token,
+            data.amountInWithFee,
+            data.amountOut,
+            data.price,
+            0,
+            true,
             txn_model,
         )
         .await?;
@@ -413,9 +826,13 @@ impl Evt {
 
     async fn handle_graduated_evt(
         &self,
+        tx: &DatabaseTransaction,
         raw_log: RawLog,
         txn_model: db_evt_txn_log::Model,
     ) -> LibResult<()> {
+        if is_handled(tx, &txn_model).await? {
+            return Ok(());
+        }
         let data = consts::FACTORY::Graduated::decode_raw_log(
             raw_log.topics,
             raw_log.data.as_ref(),
@@ -426,7 +843,7 @@ impl Evt {
 
         // 1. update token_info
         // 2. update token_summary
-        let tx = self.store.db_pool.begin().await?;
+        // 3. price and liquidity from the pair reserves
         db_token_info::Entity::update_many()
             .filter(db_token_info::Column::TokenAddress.eq(&token))
             .col_expr(db_token_info::Column::IsLaunched, Expr::value(true))
@@ -434,18 +851,20 @@ impl Evt {
                 db_token_info::Column::LaunchTs,
                 Expr::value(txn_model.block_time),
             )
-            .exec(&tx)
+            .exec(tx)
             .await?;
         db_token_summary::Entity::update_many()
             .filter(db_token_summary::Column::TokenAddress.eq(&token))
             .col_expr(
                 db_token_summary::Column::UniswapPool,
-                Expr::value(uniswap_pool),
+                Expr::value(uniswap_pool.clone()),
             )
-            .exec(&tx)
+            .exec(tx)
             .await?;
-        txn_model.into_active_model().insert(&tx).await?;
-        tx.commit().await?;
+        // the Sync of the initial liquidity comes before this log, read the reserves instead
+        let block = Some(BlockId::number(txn_model.block_number as u64));
+        sync_pair_summary(tx, &uniswap_pool, block).await?;
+        txn_model.into_active_model().insert(tx).await?;
 
         Ok(())
     }
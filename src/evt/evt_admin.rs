@@ -0,0 +1,265 @@
+use alloy::primitives::{keccak256, B256};
+use alloy::rpc::types::RawLog;
+use alloy::sol_types::SolEvent;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{ActiveModelTrait, DatabaseTransaction, IntoActiveModel};
+
+use super::evt_trade::is_handled;
+use crate::core::consts::{self, FACTORY, PROXY};
+use crate::entity::*;
+use crate::svc::{Alert, Severity, NOTIFIER};
+use crate::util::{LibError, LibResult};
+
+/// Alerts of handled admin events, sent once their transaction is committed.
+#[derive(Default)]
+pub struct AdminAlerts {
+    recent: Vec<Alert>,
+    /// events older than `ALERT_MAX_AGE`
+    missed: Vec<Alert>,
+}
+
+impl AdminAlerts {
+    pub fn extend(&mut self, other: AdminAlerts) {
+        self.recent.extend(other.recent);
+        self.missed.extend(other.missed);
+    }
+
+    /// Send every recent alert, and the missed ones as a single alert.
+    pub fn send(self) {
+        for alert in self.recent {
+            NOTIFIER.send(alert);
+        }
+        if let Some(alert) = missed_alert(&self.missed) {
+            NOTIFIER.send(alert);
+        }
+    }
+}
+
+/// Record a role, ownership, initialization or upgrade event of the factory in
+/// `factory_admin_log` and add an alert to `alerts`, of high severity when it touches a
+/// privileged role or the contract itself.
+pub async fn handle_admin(
+    tx: &DatabaseTransaction,
+    raw_log: RawLog,
+    txn_model: db_evt_txn_log::Model,
+    alerts: &mut AdminAlerts,
+) -> LibResult<()> {
+    if is_handled(tx, &txn_model).await? {
+        return Ok(());
+    }
+    let topics = raw_log.topics.clone();
+    let data = raw_log.data.as_ref();
+    let topic = topics
+        .first()
+        .copied()
+        .ok_or_else(|| LibError::InternalError("log without topic".to_string()))?;
+
+    let mut admin_model = db_factory_admin_log::ActiveModel {
+        block_number: Set(txn_model.block_number),
+        txn_index: Set(txn_model.txn_index),
+        log_index: Set(txn_model.log_index),
+        block_time: Set(txn_model.block_time),
+        txn_hash: Set(txn_model.txn_hash.clone()),
+        event: Set("".to_string()),
+        role: Set(None),
+        account: Set(None),
+        sender: Set(None),
+        prev_value: Set(None),
+        new_value: Set(None),
+    };
+    let (event, privileged) = match topic {
+        FACTORY::RoleGranted::SIGNATURE_HASH => {
+            let v = FACTORY::RoleGranted::decode_raw_log(topics, data, true)?;
+            let role = role_name(v.role);
+            admin_model.account = Set(Some(format!("{:#x}", v.account)));
+            admin_model.sender = Set(Some(format!("{:#x}", v.sender)));
+            let privileged = is_privileged(&role);
+            admin_model.role = Set(Some(role));
+            ("RoleGranted", privileged)
+        }
+        FACTORY::RoleRevoked::SIGNATURE_HASH => {
+            let v = FACTORY::RoleRevoked::decode_raw_log(topics, data, true)?;
+            let role = role_name(v.role);
+            admin_model.account = Set(Some(format!("{:#x}", v.account)));
+            admin_model.sender = Set(Some(format!("{:#x}", v.sender)));
+            let privileged = is_privileged(&role);
+            admin_model.role = Set(Some(role));
+            ("RoleRevoked", privileged)
+        }
+        FACTORY::RoleAdminChanged::SIGNATURE_HASH => {
+            let v = FACTORY::RoleAdminChanged::decode_raw_log(topics, data, true)?;
+            let role = role_name(v.role);
+            admin_model.prev_value = Set(Some(role_name(v.previousAdminRole)));
+            admin_model.new_value = Set(Some(role_name(v.newAdminRole)));
+            let privileged = is_privileged(&role);
+            admin_model.role = Set(Some(role));
+            ("RoleAdminChanged", privileged)
+        }
+        FACTORY::OwnershipTransferStarted::SIGNATURE_HASH => {
+            let v = FACTORY::OwnershipTransferStarted::decode_raw_log(topics, data, true)?;
+            admin_model.prev_value = Set(Some(format!("{:#x}", v.previousOwner)));
+            admin_model.new_value = Set(Some(format!("{:#x}", v.newOwner)));
+            ("OwnershipTransferStarted", true)
+        }
+        FACTORY::OwnershipTransferred::SIGNATURE_HASH => {
+            let v = FACTORY::OwnershipTransferred::decode_raw_log(topics, data, true)?;
+            admin_model.prev_value = Set(Some(format!("{:#x}", v.previousOwner)));
+            admin_model.new_value = Set(Some(format!("{:#x}", v.newOwner)));
+            ("OwnershipTransferred", true)
+        }
+        FACTORY::Initialized::SIGNATURE_HASH => {
+            let v = FACTORY::Initialized::decode_raw_log(topics, data, true)?;
+            admin_model.new_value = Set(Some(v.version.to_string()));
+            ("Initialized", true)
+        }
+        PROXY::Upgraded::SIGNATURE_HASH => {
+            let v = PROXY::Upgraded::decode_raw_log(topics, data, true)?;
+            admin_model.new_value = Set(Some(format!("{:#x}", v.implementation)));
+            ("Upgraded", true)
+        }
+        _ => {
+            return Err(LibError::InternalError(format!(
+                "unknown factory admin evt {topic:#x}"
+            )))
+        }
+    };
+    admin_model.event = Set(event.to_string());
+    let admin_model = admin_model.insert(tx).await?;
+    txn_model.into_active_model().insert(tx).await?;
+
+    let severity = if privileged {
+        Severity::High
+    } else {
+        Severity::Info
+    };
+    let alert = alert(&admin_model, severity);
+    let age = chrono::Utc::now().timestamp() - admin_model.block_time;
+    if age <= consts::ALERT_MAX_AGE {
+        alerts.recent.push(alert);
+    } else {
+        alerts.missed.push(alert);
+    }
+    Ok(())
+}
+
+/// Name of a factory role constant, the hex value for unknown roles.
+fn role_name(role: B256) -> String {
+    consts::FACTORY_ROLES
+        .iter()
+        .find(|(name, _)| role_hash(name) == role)
+        .map(|(name, _)| name.to_string())
+        .unwrap_or_else(|| format!("{role:#x}"))
+}
+
+fn role_hash(name: &str) -> B256 {
+    if name == "DEFAULT_ADMIN_ROLE" {
+        B256::ZERO
+    } else {
+        keccak256(name)
+    }
+}
+
+fn is_privileged(role: &str) -> bool {
+    consts::FACTORY_ROLES
+        .iter()
+        .any(|(name, privileged)| *name == role && *privileged)
+}
+
+fn alert(admin_model: &db_factory_admin_log::Model, severity: Severity) -> Alert {
+    let fields = [
+        ("role", &admin_model.role),
+        ("account", &admin_model.account),
+        ("sender", &admin_model.sender),
+        ("prev", &admin_model.prev_value),
+        ("new", &admin_model.new_value),
+    ];
+    let mut detail = format!(
+        "block={}, txn={}",
+        admin_model.block_number, admin_model.txn_hash
+    );
+    for (name, value) in fields {
+        if let Some(value) = value {
+            detail.push_str(&format!(", {name}={value}"));
+        }
+    }
+    Alert {
+        severity,
+        title: format!("factory {}", admin_model.event),
+        detail,
+    }
+}
+
+/// One alert listing the events that were missed while catching up, of high severity if any
+/// of them is.
+fn missed_alert(missed: &[Alert]) -> Option<Alert> {
+    if missed.is_empty() {
+        return None;
+    }
+    let severity = if missed.iter().any(|v| v.severity == Severity::High) {
+        Severity::High
+    } else {
+        Severity::Info
+    };
+    let detail = missed
+        .iter()
+        .map(|v| format!("{}: {}", v.title, v.detail))
+        .collect::<Vec<_>>()
+        .join("; ");
+    Some(Alert {
+        severity,
+        title: format!(
+            "{} factory admin events missed while catching up",
+            missed.len()
+        ),
+        detail,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_role_name() {
+        assert_eq!(role_name(B256::ZERO), "DEFAULT_ADMIN_ROLE");
+        let admin_role: B256 = "0xa49807205ce4d355092ef5a8a18f56e8913cf4a201fbe287825b095693c21775"
+            .parse()
+            .unwrap();
+        assert_eq!(role_name(admin_role), "ADMIN_ROLE");
+        let fee_role: B256 = "0x6c0757dc3e6b28b2580c03fd9e96c274acf4f99d91fbec9b418fa1d70604ff1c"
+            .parse()
+            .unwrap();
+        assert_eq!(role_name(fee_role), "FEE_MANAGER_ROLE");
+        let unknown = B256::repeat_byte(1);
+        assert_eq!(role_name(unknown), format!("{unknown:#x}"));
+        assert!(is_privileged(&role_name(admin_role)));
+        assert!(!is_privileged("WHITELIST_MANAGER_ROLE"));
+        assert!(!is_privileged(&role_name(unknown)));
+    }
+
+    #[test]
+    fn t_missed_alert() {
+        assert!(missed_alert(&[]).is_none());
+        let alert = |severity, title: &str| Alert {
+            severity,
+            title: title.to_string(),
+            detail: "block=1".to_string(),
+        };
+        let missed = [
+            alert(Severity::Info, "factory RoleGranted"),
+            alert(Severity::High, "factory Upgraded"),
+        ];
+        let summary = missed_alert(&missed).unwrap();
+        assert_eq!(summary.severity, Severity::High);
+        assert_eq!(
+            summary.title,
+            "2 factory admin events missed while catching up"
+        );
+        assert_eq!(
+            summary.detail,
+            "factory RoleGranted: block=1; factory Upgraded: block=1"
+        );
+        let summary = missed_alert(&missed[..1]).unwrap();
+        assert_eq!(summary.severity, Severity::Info);
+    }
+}
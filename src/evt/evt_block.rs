@@ -0,0 +1,94 @@
+use std::collections::{BTreeSet, HashMap};
+
+use alloy::eips::BlockNumberOrTag;
+use alloy::providers::Provider;
+use alloy::rpc::client::BatchRequest;
+use alloy::rpc::types::{Block, Log};
+
+use super::Evt;
+use crate::core::consts;
+use crate::entity::*;
+use crate::util::{LibError, LibResult};
+
+impl Evt {
+    /// Resolve hash and timestamp of the blocks of `logs` and of `latest_block`.
+    ///
+    /// Every block is resolved once. Logs that carry `block_timestamp` are used as they are,
+    /// the rest comes from the header cache or from batched `eth_getBlockByNumber` requests.
+    pub(super) async fn blocks(
+        &self,
+        logs: &[Log],
+        latest_block: u64,
+    ) -> LibResult<HashMap<u64, db_evt_block_log::Model>> {
+        let mut blocks = HashMap::new();
+        for log in logs.iter() {
+            if let (Some(number), Some(hash), Some(ts)) =
+                (log.block_number, log.block_hash, log.block_timestamp)
+            {
+                blocks.insert(
+                    number,
+                    db_evt_block_log::Model {
+                        block_number: number as i64,
+                        block_hash: format!("{hash:#x}"),
+                        block_time: ts as i64,
+                    },
+                );
+            }
+        }
+
+        let mut missing = BTreeSet::new();
+        {
+            let mut cache = self.block_cache.lock().unwrap();
+            let numbers = logs.iter().filter_map(|v| v.block_number);
+            for number in numbers.chain([latest_block]) {
+                if blocks.contains_key(&number) {
+                    continue;
+                }
+                match cache.get(&number) {
+                    Some(block) => {
+                        blocks.insert(number, block.clone());
+                    }
+                    None => {
+                        missing.insert(number);
+                    }
+                }
+            }
+        }
+
+        let missing: Vec<u64> = missing.into_iter().collect();
+        for chunk in missing.chunks(consts::BLOCK_BATCH_SIZE) {
+            let mut batch = BatchRequest::new(self.provider.client());
+            let mut waiters = Vec::with_capacity(chunk.len());
+            for number in chunk {
+                let params = (BlockNumberOrTag::Number(*number), false);
+                let waiter = batch.add_call::<_, Option<Block>>("eth_getBlockByNumber", &params)?;
+                waiters.push((*number, waiter));
+            }
+            batch.send().await?;
+            for (number, waiter) in waiters {
+                let block = waiter
+                    .await?
+                    .ok_or_else(|| LibError::InternalError(format!("block {number} not found")))?;
+                blocks.insert(
+                    number,
+                    db_evt_block_log::Model {
+                        block_number: number as i64,
+                        block_hash: format!("{:#x}", block.header.hash),
+                        block_time: block.header.timestamp as i64,
+                    },
+                );
+            }
+        }
+
+        let mut cache = self.block_cache.lock().unwrap();
+        for block in blocks.values() {
+            cache.put(block.block_number as u64, block.clone());
+        }
+        Ok(blocks)
+    }
+
+    /// forget cached headers, they may belong to a fork that was reorganized away
+    pub(super) fn clear_block_cache(&self) {
+        self.block_cache.lock().unwrap().clear();
+    }
+}
@@ -0,0 +1,314 @@
+use alloy::eips::BlockId;
+use alloy::rpc::types::RawLog;
+use alloy::sol_types::SolEvent;
+use sea_orm::prelude::Expr;
+use sea_orm::sea_query::OnConflict;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseTransaction, DbBackend, EntityTrait,
+    IntoActiveModel, PaginatorTrait, QueryFilter, QueryOrder, Statement, TransactionTrait,
+};
+
+use super::evt_trade::is_handled;
+use super::Evt;
+use crate::core::consts::{self, FACTORY};
+use crate::entity::*;
+use crate::svc::TOKEN;
+use crate::util::{LibError, LibResult};
+
+impl Evt {
+    /// Seed factory_config from the factory view functions at `INIT_BLOCK`.
+    ///
+    /// Only parameters without any history are seeded, later changes come from the events.
+    /// Changes between `INIT_BLOCK` and the cursor block were not indexed, a value that differs
+    /// at the cursor block is seeded as a second version from there.
+    pub(super) async fn seed_config(&self) -> LibResult<()> {
+        let init_block = *consts::INIT_BLOCK;
+        let cursor_block = self.get_block().await?.max(init_block);
+        let init_configs = TOKEN
+            .factory_config(Some(BlockId::number(init_block)))
+            .await?;
+        let cursor_configs = TOKEN
+            .factory_config(Some(BlockId::number(cursor_block)))
+            .await?;
+        let init_time = self.block_model(init_block).await?.block_time;
+        let cursor_time = self.block_model(cursor_block).await?.block_time;
+        let tx = self.store.db_pool.begin().await?;
+        for ((param, init_value), (_, cursor_value)) in init_configs.into_iter().zip(cursor_configs)
+        {
+            let exists = db_factory_config::Entity::find()
+                .filter(db_factory_config::Column::Param.eq(param))
+                .count(&tx)
+                .await?
+                > 0;
+            if exists {
+                continue;
+            }
+            // (from_block, to_block, block_time, value)
+            let versions = if cursor_value == init_value {
+                vec![(init_block, None, init_time, init_value)]
+            } else {
+                vec![
+                    (init_block, Some(cursor_block as i64), init_time, init_value),
+                    (cursor_block, None, cursor_time, cursor_value),
+                ]
+            };
+            for (block_number, to_block, block_time, value) in versions {
+                tracing::info!("seed factory config. {param}={value}, block: {block_number}");
+                let config_model = db_factory_config::ActiveModel {
+                    param: Set(param.to_string()),
+                    asset: Set("".to_string()),
+                    from_block: Set(block_number as i64),
+                    to_block: Set(to_block),
+                    value: Set(value),
+                    block_time: Set(block_time),
+                    txn_hash: Set("".to_string()),
+                };
+                config_model.insert(&tx).await?;
+            }
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+/// Record the parameters set by a factory administration event as new versions.
+pub async fn handle_config(
+    tx: &DatabaseTransaction,
+    raw_log: RawLog,
+    txn_model: db_evt_txn_log::Model,
+) -> LibResult<()> {
+    if is_handled(tx, &txn_model).await? {
+        return Ok(());
+    }
+    let topics = raw_log.topics.clone();
+    let data = raw_log.data.as_ref();
+    let topic = topics
+        .first()
+        .copied()
+        .ok_or_else(|| LibError::InternalError("log without topic".to_string()))?;
+
+    // (param, asset, value)
+    let configs: Vec<(&str, String, String)> = match topic {
+        FACTORY::FeeParametersSet::SIGNATURE_HASH => {
+            let v = FACTORY::FeeParametersSet::decode_raw_log(topics, data, true)?;
+            vec![
+                ("tax_vault", "".to_string(), format!("{:#x}", v.newVault)),
+                ("buy_fee", "".to_string(), v.buyFee.to_string()),
+                ("sell_fee", "".to_string(), v.sellFee.to_string()),
+            ]
+        }
+        FACTORY::LaunchFeeSet::SIGNATURE_HASH => {
+            let v = FACTORY::LaunchFeeSet::decode_raw_log(topics, data, true)?;
+            vec![(
+                "launch_fee",
+                format!("{:#x}", v.asset),
+                v.newLaunchFee.to_string(),
+            )]
+        }
+        FACTORY::LaunchPointPercentageSet::SIGNATURE_HASH => {
+            let v = FACTORY::LaunchPointPercentageSet::decode_raw_log(topics, data, true)?;
+            vec![(
+                "launch_point_percentage",
+                "".to_string(),
+                v.newLaunchPointPercentage.to_string(),
+            )]
+        }
+        FACTORY::MigrationTaxSet::SIGNATURE_HASH => {
+            let v = FACTORY::MigrationTaxSet::decode_raw_log(topics, data, true)?;
+            vec![(
+                "migration_tax",
+                "".to_string(),
+                v.newMigrationTax.to_string(),
+            )]
+        }
+        FACTORY::InitialSupplySet::SIGNATURE_HASH => {
+            let v = FACTORY::InitialSupplySet::decode_raw_log(topics, data, true)?;
+            vec![("initial_supply", "".to_string(), v.newSupply.to_string())]
+        }
+        FACTORY::InitialYSet::SIGNATURE_HASH => {
+            let v = FACTORY::InitialYSet::decode_raw_log(topics, data, true)?;
+            vec![("initial_y", "".to_string(), v.newInitialY.to_string())]
+        }
+        FACTORY::MaxFunCurveSet::SIGNATURE_HASH => {
+            let v = FACTORY::MaxFunCurveSet::decode_raw_log(topics, data, true)?;
+            vec![(
+                "max_fun_curve",
+                "".to_string(),
+                format!("{:#x}", v.newMaxFunCurve),
+            )]
+        }
+        FACTORY::MaxFunManagerSet::SIGNATURE_HASH => {
+            let v = FACTORY::MaxFunManagerSet::decode_raw_log(topics, data, true)?;
+            vec![(
+                "max_fun_manager",
+                "".to_string(),
+                format!("{:#x}", v.newManager),
+            )]
+        }
+        FACTORY::SignerSet::SIGNATURE_HASH => {
+            let v = FACTORY::SignerSet::decode_raw_log(topics, data, true)?;
+            vec![("signer", "".to_string(), format!("{:#x}", v.newSigner))]
+        }
+        _ => {
+            return Err(LibError::InternalError(format!(
+                "unknown factory config evt {topic:#x}"
+            )))
+        }
+    };
+
+    for (param, asset, value) in configs {
+        set_config(tx, param, &asset, value, &txn_model).await?;
+    }
+    txn_model.into_active_model().insert(tx).await?;
+    Ok(())
+}
+
+/// Value of `param` in effect at `block_number`.
+pub async fn config_at(
+    tx: &DatabaseTransaction,
+    param: &str,
+    asset: &str,
+    block_number: i64,
+) -> LibResult<Option<String>> {
+    let versions = db_factory_config::Entity::find()
+        .filter(db_factory_config::Column::Param.eq(param))
+        .filter(db_factory_config::Column::Asset.eq(asset))
+        .all(tx)
+        .await?;
+    Ok(version_at(&versions, block_number).map(|v| v.value.clone()))
+}
+
+/// The version with `from_block <= block_number < to_block`.
+fn version_at(
+    versions: &[db_factory_config::Model],
+    block_number: i64,
+) -> Option<&db_factory_config::Model> {
+    versions
+        .iter()
+        .filter(|v| v.from_block <= block_number)
+        .filter(|v| v.to_block.is_none_or(|to_block| block_number < to_block))
+        .max_by_key(|v| v.from_block)
+}
+
+/// Insert a version of `param` starting at the block of `txn_model`.
+///
+/// The version is closed by the next version if there is one, and closes the version
+/// before it, so versions line up whatever order the events are handled in.
+async fn set_config(
+    tx: &DatabaseTransaction,
+    param: &str,
+    asset: &str,
+    value: String,
+    txn_model: &db_evt_txn_log::Model,
+) -> LibResult<()> {
+    let from_block = txn_model.block_number;
+    let next = db_factory_config::Entity::find()
+        .filter(db_factory_config::Column::Param.eq(param))
+        .filter(db_factory_config::Column::Asset.eq(asset))
+        .filter(db_factory_config::Column::FromBlock.gt(from_block))
+        .order_by_asc(db_factory_config::Column::FromBlock)
+        .one(tx)
+        .await?;
+
+    let config_model = db_factory_config::ActiveModel {
+        param: Set(param.to_string()),
+        asset: Set(asset.to_string()),
+        from_block: Set(from_block),
+        to_block: Set(next.map(|v| v.from_block)),
+        value: Set(value),
+        block_time: Set(txn_model.block_time),
+        txn_hash: Set(txn_model.txn_hash.clone()),
+    };
+    // several changes in one block, the last one wins
+    let onconflict = OnConflict::columns([
+        db_factory_config::Column::Param,
+        db_factory_config::Column::Asset,
+        db_factory_config::Column::FromBlock,
+    ])
+    .update_column(db_factory_config::Column::Value)
+    .update_column(db_factory_config::Column::BlockTime)
+    .update_column(db_factory_config::Column::TxnHash)
+    .to_owned();
+    db_factory_config::Entity::insert(config_model)
+        .on_conflict(onconflict)
+        .exec(tx)
+        .await?;
+
+    let prev = db_factory_config::Entity::find()
+        .filter(db_factory_config::Column::Param.eq(param))
+        .filter(db_factory_config::Column::Asset.eq(asset))
+        .filter(db_factory_config::Column::FromBlock.lt(from_block))
+        .order_by_desc(db_factory_config::Column::FromBlock)
+        .one(tx)
+        .await?;
+    if let Some(prev) = prev {
+        db_factory_config::Entity::update_many()
+            .filter(db_factory_config::Column::Param.eq(param))
+            .filter(db_factory_config::Column::Asset.eq(asset))
+            .filter(db_factory_config::Column::FromBlock.eq(prev.from_block))
+            .col_expr(db_factory_config::Column::ToBlock, Expr::value(from_block))
+            .exec(tx)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Remove the versions set at or above `fork_block` and reopen the versions they closed,
+/// seeded versions are kept.
+pub async fn rollback_config(tx: &DatabaseTransaction, fork_block: i64) -> LibResult<()> {
+    db_factory_config::Entity::delete_many()
+        .filter(db_factory_config::Column::FromBlock.gte(fork_block))
+        .filter(db_factory_config::Column::TxnHash.ne(""))
+        .exec(tx)
+        .await?;
+    let sql = r#"
+        UPDATE factory_config c
+        SET to_block = (
+            SELECT MIN(n.from_block) FROM factory_config n
+            WHERE n.param = c.param AND n.asset = c.asset AND n.from_block > c.from_block
+        )
+        WHERE c.to_block >= $1
+    "#;
+    tx.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        sql,
+        [fork_block.into()],
+    ))
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(from_block: i64, to_block: Option<i64>, value: &str) -> db_factory_config::Model {
+        db_factory_config::Model {
+            param: "buy_fee".to_string(),
+            asset: "".to_string(),
+            from_block,
+            to_block,
+            value: value.to_string(),
+            block_time: from_block,
+            txn_hash: "".to_string(),
+        }
+    }
+
+    #[test]
+    fn t_version_at() {
+        let versions = vec![
+            version(200, None, "150"),
+            version(100, Some(150), "100"),
+            version(150, Some(200), "120"),
+        ];
+        let value = |block_number| version_at(&versions, block_number).map(|v| v.value.as_str());
+        assert_eq!(value(99), None);
+        assert_eq!(value(100), Some("100"));
+        assert_eq!(value(149), Some("100"));
+        assert_eq!(value(150), Some("120"));
+        assert_eq!(value(199), Some("120"));
+        assert_eq!(value(200), Some("150"));
+        assert_eq!(value(10_000), Some("150"));
+    }
+}
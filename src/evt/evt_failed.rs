@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+
+use sea_orm::prelude::Expr;
+use sea_orm::sea_query::OnConflict;
+use sea_orm::ActiveValue::{NotSet, Set};
+use sea_orm::{
+    ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter, QueryOrder, TransactionTrait,
+};
+
+use super::evt::AfterCommit;
+use super::Evt;
+use crate::core::consts;
+use crate::entity::db_evt_failed_log::{STATUS_EXHAUSTED, STATUS_PENDING, STATUS_RESOLVED};
+use crate::entity::*;
+use crate::util::{LibError, LibResult};
+
+/// Save a log whose handler failed, it is retried by `Evt::retry_failed` later.
+pub async fn save_failed(
+    tx: &DatabaseTransaction,
+    txn_model: &db_evt_txn_log::Model,
+    err: &LibError,
+) -> LibResult<()> {
+    let now_ts = chrono::Utc::now().timestamp();
+    let failed_model = db_evt_failed_log::ActiveModel {
+        id: NotSet,
+        block_number: Set(txn_model.block_number),
+        txn_index: Set(txn_model.txn_index),
+        log_index: Set(txn_model.log_index),
+        block_time: Set(txn_model.block_time),
+        txn_hash: Set(txn_model.txn_hash.clone()),
+        address: Set(txn_model.address.clone()),
+        topic_0: Set(txn_model.topic_0.clone()),
+        topic_1: Set(txn_model.topic_1.clone()),
+        topic_2: Set(txn_model.topic_2.clone()),
+        topic_3: Set(txn_model.topic_3.clone()),
+        data: Set(txn_model.data.clone()),
+        error: Set(err.to_string()),
+        attempts: Set(1),
+        status: Set(STATUS_PENDING),
+        next_retry_ts: Set(now_ts + retry_delay(1)),
+        create_ts: Set(now_ts),
+        update_ts: Set(now_ts),
+    };
+    // a log that is scanned again keeps its retry state
+    let onconflict = OnConflict::columns([
+        db_evt_failed_log::Column::BlockNumber,
+        db_evt_failed_log::Column::TxnIndex,
+        db_evt_failed_log::Column::LogIndex,
+    ])
+    .update_column(db_evt_failed_log::Column::Error)
+    .update_column(db_evt_failed_log::Column::UpdateTs)
+    .to_owned();
+    db_evt_failed_log::Entity::insert(failed_model)
+        .on_conflict(onconflict)
+        .exec(tx)
+        .await?;
+    Ok(())
+}
+
+impl Evt {
+    /// Retry all failed logs that are due, oldest log first.
+    pub async fn retry_failed(&self) -> LibResult<()> {
+        let now_ts = chrono::Utc::now().timestamp();
+        let failed_logs = db_evt_failed_log::Entity::find()
+            .filter(db_evt_failed_log::Column::Status.eq(STATUS_PENDING))
+            .filter(db_evt_failed_log::Column::NextRetryTs.lte(now_ts))
+            .order_by_asc(db_evt_failed_log::Column::BlockNumber)
+            .order_by_asc(db_evt_failed_log::Column::TxnIndex)
+            .order_by_asc(db_evt_failed_log::Column::LogIndex)
+            .all(&self.store.db_pool)
+            .await?;
+        for failed in failed_logs {
+            self.retry_failed_log(failed).await?;
+        }
+        Ok(())
+    }
+
+    /// Handle a failed log again, returns whether the retry succeeded.
+    ///
+    /// On failure the next retry is scheduled with exponential backoff, after
+    /// `MAX_RETRY_ATTEMPTS` attempts the log is given up.
+    pub async fn retry_failed_log(&self, failed: db_evt_failed_log::Model) -> LibResult<bool> {
+        let now_ts = chrono::Utc::now().timestamp();
+        let tx = self.store.db_pool.begin().await?;
+        let mut after_commit = AfterCommit::default();
+        match self
+            .handle_log(&tx, failed.txn_model(), &HashMap::new(), &mut after_commit)
+            .await
+        {
+            Ok(_) => {
+                db_evt_failed_log::Entity::update_many()
+                    .filter(db_evt_failed_log::Column::Id.eq(failed.id))
+                    .col_expr(
+                        db_evt_failed_log::Column::Status,
+                        Expr::value(STATUS_RESOLVED),
+                    )
+                    .col_expr(db_evt_failed_log::Column::UpdateTs, Expr::value(now_ts))
+                    .exec(&tx)
+                    .await?;
+                tx.commit().await?;
+                after_commit.apply();
+                tracing::info!(
+                    "retry failed evt success. id={}, txn_hash={}",
+                    failed.id,
+                    failed.txn_hash
+                );
+                Ok(true)
+            }
+            Err(e) => {
+                tx.rollback().await?;
+                let attempts = failed.attempts + 1;
+                let status = if attempts >= consts::MAX_RETRY_ATTEMPTS {
+                    tracing::error!(
+                        "retry failed evt exhausted. id={}, txn_hash={}, err={e}",
+                        failed.id,
+                        failed.txn_hash
+                    );
+                    STATUS_EXHAUSTED
+                } else {
+                    tracing::warn!(
+                        "retry failed evt. id={}, txn_hash={}, attempts={attempts}, err={e}",
+                        failed.id,
+                        failed.txn_hash
+                    );
+                    STATUS_PENDING
+                };
+                db_evt_failed_log::Entity::update_many()
+                    .filter(db_evt_failed_log::Column::Id.eq(failed.id))
+                    .col_expr(db_evt_failed_log::Column::Error, Expr::value(e.to_string()))
+                    .col_expr(db_evt_failed_log::Column::Attempts, Expr::value(attempts))
+                    .col_expr(db_evt_failed_log::Column::Status, Expr::value(status))
+                    .col_expr(
+                        db_evt_failed_log::Column::NextRetryTs,
+                        Expr::value(now_ts + retry_delay(attempts)),
+                    )
+                    .col_expr(db_evt_failed_log::Column::UpdateTs, Expr::value(now_ts))
+                    .exec(&self.store.db_pool)
+                    .await?;
+                Ok(false)
+            }
+        }
+    }
+}
+
+/// seconds to wait before the next retry of a log that failed `attempts` times
+fn retry_delay(attempts: i32) -> i64 {
+    let exp = (attempts.max(1) - 1).min(30) as u32;
+    consts::RETRY_BASE_DELAY
+        .saturating_mul(2i64.pow(exp))
+        .min(consts::RETRY_MAX_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_retry_delay() {
+        assert_eq!(retry_delay(1), 60);
+        assert_eq!(retry_delay(2), 120);
+        assert_eq!(retry_delay(5), 960);
+        assert_eq!(retry_delay(20), 86400);
+        assert_eq!(retry_delay(100), 86400);
+    }
+}
@@ -0,0 +1,262 @@
+use sea_orm::{ConnectionTrait, DatabaseTransaction, DbBackend, Iterable, Statement, Value};
+
+use crate::util::{LibError, LibResult, PeriodType};
+
+/// Candles a rebuild corrected in one table and period.
+#[derive(Debug)]
+pub struct KlineDiff {
+    pub table: &'static str,
+    pub period: PeriodType,
+    /// candles that were missing
+    pub added: i64,
+    /// candles whose values differed
+    pub changed: i64,
+    /// candles without any trade left
+    pub removed: i64,
+}
+
+/// Rebuild `kline_5m` and the `kline` candles of every period out of evt_trade_log, for
+/// `token` or all tokens, between `from_ts` and `to_ts` (exclusive, open-ended when `None`).
+///
+/// The range is widened to whole candles of each period, the stored candles are deleted and
+/// computed again in bulk within `tx`. Used where the candles are known to be stale, after a
+/// rollback or a late trade, see `repair_klines` to find out what was wrong.
+///
+/// The launch price in evt_token_log is folded in as a zero volume trade, the same way
+/// handle_launched_evt opens the first candle.
+pub async fn rebuild_klines(
+    tx: &DatabaseTransaction,
+    token: Option<&str>,
+    from_ts: i64,
+    to_ts: Option<i64>,
+) -> LibResult<()> {
+    for (table, period) in targets() {
+        let query = CandleQuery::new(table, period, token, from_ts, to_ts);
+        execute(tx, &query.delete_sql(), query.values()).await?;
+        let sql = format!(
+            "INSERT INTO {table} ({}) {}",
+            query.columns(),
+            query.select_sql()
+        );
+        execute(tx, &sql, query.values()).await?;
+    }
+    Ok(())
+}
+
+/// Rebuild the candles like `rebuild_klines` and report what was corrected.
+///
+/// The candles are computed into a temporary table, compared with the stored ones and swapped
+/// in, with the kline tables locked against concurrent trades of the running indexer.
+pub async fn repair_klines(
+    tx: &DatabaseTransaction,
+    token: Option<&str>,
+    from_ts: i64,
+    to_ts: Option<i64>,
+) -> LibResult<Vec<KlineDiff>> {
+    execute(
+        tx,
+        "LOCK TABLE kline_5m, kline IN EXCLUSIVE MODE",
+        Vec::new(),
+    )
+    .await?;
+    let mut diffs = Vec::new();
+    for (table, period) in targets() {
+        let query = CandleQuery::new(table, period, token, from_ts, to_ts);
+        diffs.push(repair_period(tx, &query).await?);
+    }
+    Ok(diffs)
+}
+
+/// `kline_5m` and every period of `kline`
+fn targets() -> impl Iterator<Item = (&'static str, PeriodType)> {
+    std::iter::once(("kline_5m", PeriodType::M5))
+        .chain(PeriodType::iter().map(|period| ("kline", period)))
+}
+
+async fn repair_period(tx: &DatabaseTransaction, query: &CandleQuery<'_>) -> LibResult<KlineDiff> {
+    let table = query.table;
+    let columns = query.columns();
+    let period_cond = query.period_cond();
+    execute(
+        tx,
+        &format!("CREATE TEMPORARY TABLE kline_rebuild (LIKE {table})"),
+        Vec::new(),
+    )
+    .await?;
+    let sql = format!(
+        "INSERT INTO kline_rebuild ({columns}) {}",
+        query.select_sql()
+    );
+    execute(tx, &sql, query.values()).await?;
+
+    let sql = format!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM kline_rebuild n WHERE NOT EXISTS (
+                SELECT 1 FROM {table} o
+                WHERE o.token_address = n.token_address AND o.open_ts = n.open_ts {period_cond}
+            )) AS added,
+            (SELECT COUNT(*) FROM kline_rebuild n
+                JOIN {table} o ON o.token_address = n.token_address AND o.open_ts = n.open_ts {period_cond}
+                WHERE (n.close_ts, n.open, n.high, n.low, n.close, n.volume, n.amount, n.txn_num,
+                    n.open_usd, n.high_usd, n.low_usd, n.close_usd, n.amount_usd)
+                    IS DISTINCT FROM (o.close_ts, o.open, o.high, o.low, o.close, o.volume, o.amount, o.txn_num,
+                    o.open_usd, o.high_usd, o.low_usd, o.close_usd, o.amount_usd)
+            ) AS changed,
+            (SELECT COUNT(*) FROM {table} o WHERE {} AND NOT EXISTS (
+                SELECT 1 FROM kline_rebuild n
+                WHERE n.token_address = o.token_address AND n.open_ts = o.open_ts
+            )) AS removed
+        "#,
+        query.stored_cond()
+    );
+    let row = tx
+        .query_one(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            sql,
+            query.values(),
+        ))
+        .await?
+        .ok_or_else(|| LibError::InternalError("kline diff without result".to_string()))?;
+    let diff = KlineDiff {
+        table,
+        period: query.period,
+        added: row.try_get("", "added")?,
+        changed: row.try_get("", "changed")?,
+        removed: row.try_get("", "removed")?,
+    };
+
+    execute(tx, &query.delete_sql(), query.values()).await?;
+    execute(
+        tx,
+        &format!("INSERT INTO {table} ({columns}) SELECT {columns} FROM kline_rebuild"),
+        Vec::new(),
+    )
+    .await?;
+    execute(tx, "DROP TABLE kline_rebuild", Vec::new()).await?;
+    Ok(diff)
+}
+
+/// SQL of the candles of one table and period, bound to `values`.
+struct CandleQuery<'a> {
+    table: &'static str,
+    period: PeriodType,
+    token: Option<&'a str>,
+    start_ts: i64,
+    end_ts: i64,
+}
+
+impl<'a> CandleQuery<'a> {
+    fn new(
+        table: &'static str,
+        period: PeriodType,
+        token: Option<&'a str>,
+        from_ts: i64,
+        to_ts: Option<i64>,
+    ) -> Self {
+        Self {
+            table,
+            period,
+            token,
+            start_ts: period.open_ts(from_ts),
+            end_ts: to_ts.map_or(i64::MAX, |v| period.close_ts(v - 1)),
+        }
+    }
+
+    /// `$1` token or NULL for all tokens, `$2` and `$3` the widened time range
+    fn values(&self) -> Vec<Value> {
+        vec![
+            self.token.map(str::to_string).into(),
+            self.start_ts.into(),
+            self.end_ts.into(),
+        ]
+    }
+
+    /// kline keeps every period in one table
+    fn is_kline(&self) -> bool {
+        self.table == "kline"
+    }
+
+    fn columns(&self) -> String {
+        let period_column = if self.is_kline() { "period, " } else { "" };
+        format!(
+            "token_address, {period_column}open_ts, close_ts, open, high, low, close, volume, amount, txn_num, \
+            open_usd, high_usd, low_usd, close_usd, amount_usd"
+        )
+    }
+
+    fn period_cond(&self) -> String {
+        if self.is_kline() {
+            format!("AND o.period = '{}'", self.period.name())
+        } else {
+            String::new()
+        }
+    }
+
+    /// the stored candles of the range, aliased `o`
+    fn stored_cond(&self) -> String {
+        format!(
+            "($1::VARCHAR IS NULL OR o.token_address = $1) AND o.open_ts BETWEEN $2 AND $3 {}",
+            self.period_cond()
+        )
+    }
+
+    fn delete_sql(&self) -> String {
+        format!("DELETE FROM {} o WHERE {}", self.table, self.stored_cond())
+    }
+
+    /// the candles of the range computed from the trades, in the order of `columns`
+    fn select_sql(&self) -> String {
+        let period_value = if self.is_kline() {
+            format!("'{}', ", self.period.name())
+        } else {
+            String::new()
+        };
+        let open_ts = self.period.open_ts_sql("block_time");
+        let close_ts = self.period.close_ts_sql("open_ts");
+        let range_cond =
+            "($1::VARCHAR IS NULL OR token_address = $1) AND block_time BETWEEN $2 AND $3";
+        format!(
+            r#"
+            SELECT
+                token_address,
+                {period_value}open_ts,
+                {close_ts},
+                (ARRAY_AGG(price ORDER BY block_number, txn_index, log_index))[1],
+                MAX(price),
+                MIN(price),
+                (ARRAY_AGG(price ORDER BY block_number DESC, txn_index DESC, log_index DESC))[1],
+                SUM(volume),
+                SUM(volume * price),
+                SUM(txn_num),
+                (ARRAY_AGG(price_usd ORDER BY block_number, txn_index, log_index))[1],
+                MAX(price_usd),
+                MIN(price_usd),
+                (ARRAY_AGG(price_usd ORDER BY block_number DESC, txn_index DESC, log_index DESC))[1],
+                SUM(volume * price_usd)
+            FROM (
+                SELECT token_address, {open_ts} AS open_ts, block_number, txn_index, log_index,
+                    price_token AS price, price AS price_usd, amount0 AS volume, 1 AS txn_num
+                FROM evt_trade_log
+                WHERE {range_cond}
+                UNION ALL
+                SELECT token_address, {open_ts} AS open_ts, block_number, txn_index, log_index,
+                    init_price AS price, init_price_usd AS price_usd, 0 AS volume, 0 AS txn_num
+                FROM evt_token_log
+                WHERE {range_cond}
+            ) t
+            GROUP BY token_address, open_ts
+            "#
+        )
+    }
+}
+
+async fn execute(tx: &DatabaseTransaction, sql: &str, values: Vec<Value>) -> LibResult<()> {
+    tx.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        sql,
+        values,
+    ))
+    .await?;
+    Ok(())
+}
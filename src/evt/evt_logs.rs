@@ -0,0 +1,162 @@
+use std::sync::atomic::Ordering;
+
+use alloy::providers::Provider;
+use alloy::rpc::types::{Filter, Log};
+use alloy::transports::{RpcError, TransportErrorKind};
+
+use super::Evt;
+use crate::core::{consts, RPC};
+use crate::util::{LibError, LibResult};
+
+/// parts of the error messages providers return when a `get_logs` request is too large
+const RANGE_ERRORS: [&str; 10] = [
+    "query returned more than",
+    "response size",
+    "too many results",
+    "too many logs",
+    "block range",
+    "range too large",
+    "range is too large",
+    "is limited to",
+    "max results",
+    "result window",
+];
+
+impl Evt {
+    /// Get the logs of `filter` in `[start_block, end_block]`.
+    ///
+    /// The range is split into requests of `log_range` blocks. When the provider rejects a
+    /// request as too large the range is halved and the request repeated, after every
+    /// success it grows again by a quarter up to `MAX_BLOCK_RANGE`.
+    pub(super) async fn get_logs(
+        &self,
+        filter: &Filter,
+        start_block: u64,
+        end_block: u64,
+    ) -> LibResult<Vec<Log>> {
+        let mut logs = Vec::new();
+        let mut from_block = start_block;
+        while from_block <= end_block {
+            let range = self.log_range.load(Ordering::Relaxed);
+            let to_block = end_block.min(from_block + range - 1);
+            let chunk = filter.clone().from_block(from_block).to_block(to_block);
+            match self.provider.get_logs(&chunk).await {
+                Ok(v) => {
+                    if *consts::RPC_CROSS_CHECK {
+                        cross_check_logs(&chunk, &v).await?;
+                    }
+                    logs.extend(v);
+                    from_block = to_block + 1;
+                    self.log_range.store(grow_range(range), Ordering::Relaxed);
+                }
+                Err(e) if to_block > from_block && is_range_error(&e) => {
+                    let range = (to_block - from_block).div_ceil(2);
+                    tracing::warn!(
+                        "get logs range too large, retry with {range} blocks. start block: {from_block}, err={e}"
+                    );
+                    self.log_range.store(range, Ordering::Relaxed);
+                }
+                Err(e) => {
+                    tracing::error!("get logs err={e}");
+                    return Err(LibError::AlloyEthersError(e));
+                }
+            }
+        }
+        Ok(logs)
+    }
+}
+
+/// Compare `logs` with the logs a second provider returns for `filter`, a mismatch
+/// fails the range so it is fetched again.
+async fn cross_check_logs(filter: &Filter, logs: &[Log]) -> LibResult<()> {
+    let Some(provider) = RPC.alternate() else {
+        return Ok(());
+    };
+    let other = provider.get_logs(filter).await?;
+    let key = |v: &Log| (v.block_hash, v.transaction_hash, v.log_index);
+    if !logs.iter().map(key).eq(other.iter().map(key)) {
+        return Err(LibError::InternalError(format!(
+            "get_logs mismatch between providers: {} vs {} logs",
+            logs.len(),
+            other.len()
+        )));
+    }
+    Ok(())
+}
+
+fn grow_range(range: u64) -> u64 {
+    (range + range.div_ceil(4)).min(consts::MAX_BLOCK_RANGE)
+}
+
+/// whether the provider rejected a `get_logs` request because of its block range or result size
+fn is_range_error(err: &RpcError<TransportErrorKind>) -> bool {
+    let msg = match err {
+        RpcError::ErrorResp(payload) => payload.message.to_lowercase(),
+        RpcError::Transport(TransportErrorKind::HttpError(e)) => {
+            if e.status == 413 {
+                return true;
+            }
+            e.body.to_lowercase()
+        }
+        _ => return false,
+    };
+    RANGE_ERRORS.iter().any(|v| msg.contains(v))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::rpc::json_rpc::ErrorPayload;
+
+    fn error_resp(code: i64, message: &'static str) -> RpcError<TransportErrorKind> {
+        RpcError::ErrorResp(ErrorPayload {
+            code,
+            message: message.into(),
+            data: None,
+        })
+    }
+
+    #[test]
+    fn t_is_range_error() {
+        assert!(is_range_error(&error_resp(
+            -32005,
+            "query returned more than 10000 results"
+        )));
+        assert!(is_range_error(&error_resp(
+            -32602,
+            "Log response size exceeded. You can make eth_getLogs requests with up to a 2K block range"
+        )));
+        assert!(is_range_error(&error_resp(
+            -32000,
+            "block range is too wide"
+        )));
+        assert!(is_range_error(&TransportErrorKind::http_error(
+            413,
+            String::new()
+        )));
+        assert!(is_range_error(&error_resp(
+            -32600,
+            "eth_getLogs range too large, max 1000 blocks"
+        )));
+        assert!(is_range_error(&error_resp(
+            -32000,
+            "requested range exceeds maximum block range"
+        )));
+        assert!(!is_range_error(&error_resp(-32000, "header not found")));
+        assert!(!is_range_error(&error_resp(
+            -32602,
+            "invalid argument 0: hex number out of range"
+        )));
+        assert!(!is_range_error(&TransportErrorKind::http_error(
+            429,
+            "Too Many Requests".to_string()
+        )));
+    }
+
+    #[test]
+    fn t_grow_range() {
+        assert_eq!(grow_range(1), 2);
+        assert_eq!(grow_range(100), 125);
+        assert_eq!(grow_range(consts::MAX_BLOCK_RANGE), consts::MAX_BLOCK_RANGE);
+    }
+}
@@ -0,0 +1,334 @@
+use alloy::eips::BlockId;
+use alloy::rpc::types::{Filter, Log, RawLog};
+use alloy::sol_types::SolEvent;
+use rust_decimal::Decimal;
+use sea_orm::prelude::Expr;
+use sea_orm::sea_query::{Condition, OnConflict};
+use sea_orm::ActiveValue::Set;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DatabaseTransaction,
+    DbBackend, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder, Statement, TransactionTrait,
+};
+
+use super::evt_trade::is_handled;
+use super::evt_whitelist::raised_oracle;
+use super::Evt;
+use crate::core::consts::ORACLE;
+use crate::entity::*;
+use crate::svc::{OraclePrice, PriceCache, PRICE, TOKEN};
+use crate::util::LibResult;
+
+impl Evt {
+    /// AnswerUpdated logs of `[start_block, end_block]` of the aggregators behind the
+    /// raised tokens' oracles.
+    pub(super) async fn oracle_logs(
+        &self,
+        start_block: u64,
+        end_block: u64,
+    ) -> LibResult<Vec<Log>> {
+        let aggregators = PRICE.aggregators();
+        if aggregators.is_empty() {
+            return Ok(Vec::new());
+        }
+        let filter = Filter::new()
+            .address(aggregators)
+            .event("AnswerUpdated(int256,uint256,uint256)");
+        self.get_logs(&filter, start_block, end_block).await
+    }
+}
+
+/// Load the latest round of every raised token's oracle into the price cache, and store
+/// and reprice it when it is newer than the stored round.
+///
+/// Also picks up oracles set by the operator and proxies that switched to a new aggregator.
+pub async fn sync_prices(db: &DatabaseConnection) -> LibResult<()> {
+    let raised_tokens = db_raised_token::Entity::find()
+        .filter(db_raised_token::Column::Oracle.ne(""))
+        .all(db)
+        .await?;
+    for raised_token in raised_tokens {
+        let (aggregator, decimals, answer, round, updated_at) =
+            match TOKEN.oracle_latest(&raised_token.oracle).await {
+                Ok(v) => v,
+                Err(e) => {
+                    tracing::error!(
+                        "oracle latest round. asset={}, err={e}",
+                        raised_token.address
+                    );
+                    continue;
+                }
+            };
+        let price = OraclePrice {
+            asset: raised_token.address,
+            oracle: raised_token.oracle,
+            aggregator,
+            decimals,
+            price: PriceCache::answer_price(answer, decimals)?,
+            round,
+            updated_at,
+        };
+        let tx = db.begin().await?;
+        save_round(&tx, &price).await?;
+        save_price(&tx, &price).await?;
+        tx.commit().await?;
+        PRICE.set(price);
+    }
+    Ok(())
+}
+
+/// Apply a new round of an aggregator to the raised tokens it prices.
+///
+/// The new prices are added to `prices`, the price cache takes them once `tx` is committed.
+pub async fn handle_answer_updated(
+    tx: &DatabaseTransaction,
+    raw_log: RawLog,
+    txn_model: db_evt_txn_log::Model,
+    prices: &mut Vec<OraclePrice>,
+) -> LibResult<()> {
+    if is_handled(tx, &txn_model).await? {
+        return Ok(());
+    }
+    let aggregator = raw_log.address;
+    let data = ORACLE::AnswerUpdated::decode_raw_log(raw_log.topics, raw_log.data.as_ref(), true)?;
+    for cached in PRICE.by_aggregator(aggregator) {
+        let price = OraclePrice {
+            price: PriceCache::answer_price(data.current, cached.decimals)?,
+            round: data.roundId.to::<u64>(),
+            updated_at: data.updatedAt.to::<i64>(),
+            ..cached
+        };
+        save_round(tx, &price).await?;
+        if save_price(tx, &price).await? {
+            tracing::info!(
+                "raised token price updated. asset={}, price={}, round={}",
+                price.asset,
+                price.price,
+                price.round
+            );
+        }
+        prices.push(price);
+    }
+    txn_model.into_active_model().insert(tx).await?;
+    Ok(())
+}
+
+/// USD price of a raised token, from the stored price, the cache or the oracle in this order.
+///
+/// The stored price comes first, it includes rounds handled earlier in `tx` that the cache
+/// only takes after the commit.
+pub async fn raised_price(tx: &DatabaseTransaction, asset: &str) -> LibResult<Decimal> {
+    let oracle = raised_oracle(tx, asset).await?;
+    let raised_token = db_raised_token::Entity::find_by_id(asset).one(tx).await?;
+    if let Some(raised_token) = raised_token.filter(|v| v.price_ts > 0) {
+        return Ok(raised_token.price);
+    }
+    if let Some(price) = PRICE.get(asset) {
+        return Ok(price);
+    }
+    TOKEN.oracle_price(&oracle, None).await
+}
+
+/// USD price of a raised token at `ts` of `block`, from the oracle round active then, falling
+/// back to the oracle's answer at `block` when no round at or before `ts` is stored.
+pub async fn price_at(
+    tx: &DatabaseTransaction,
+    asset: &str,
+    ts: i64,
+    block: u64,
+) -> LibResult<Decimal> {
+    let oracle = raised_oracle(tx, asset).await?;
+    let round = db_oracle_price_log::Entity::find()
+        .filter(db_oracle_price_log::Column::Oracle.eq(&oracle))
+        .filter(db_oracle_price_log::Column::UpdatedAt.lte(ts))
+        .order_by_desc(db_oracle_price_log::Column::UpdatedAt)
+        .one(tx)
+        .await?;
+    match round {
+        Some(round) => Ok(round.answer),
+        None => {
+            TOKEN
+                .oracle_price(&oracle, Some(BlockId::number(block)))
+                .await
+        }
+    }
+}
+
+/// Store the rounds of every raised token's oracle updated at or after `from_ts`, walking
+/// back from the latest round through the phases of the proxy with `getRoundData`.
+///
+/// Returns the number of rounds stored.
+pub async fn backfill_rounds(db: &DatabaseConnection, from_ts: i64) -> LibResult<u64> {
+    let raised_tokens = db_raised_token::Entity::find()
+        .filter(db_raised_token::Column::Oracle.ne(""))
+        .all(db)
+        .await?;
+    let mut stored = 0;
+    for raised_token in raised_tokens {
+        let oracle = raised_token.oracle;
+        let (phase, decimals) = TOKEN.oracle_phase(&oracle).await?;
+        'phases: for phase in (1..=phase).rev() {
+            let (aggregator, latest_round) = TOKEN.phase_aggregator(&oracle, phase).await?;
+            for round in (1..=latest_round).rev() {
+                let Some((answer, updated_at)) = TOKEN.oracle_round(&oracle, phase, round).await?
+                else {
+                    continue;
+                };
+                if updated_at < from_ts {
+                    break 'phases;
+                }
+                let price = OraclePrice {
+                    asset: raised_token.address.clone(),
+                    oracle: oracle.clone(),
+                    aggregator,
+                    decimals,
+                    price: PriceCache::answer_price(answer, decimals)?,
+                    round,
+                    updated_at,
+                };
+                if save_round(db, &price).await? {
+                    stored += 1;
+                }
+            }
+        }
+        tracing::info!("oracle rounds backfilled. asset={}", raised_token.address);
+    }
+    Ok(stored)
+}
+
+/// Reprice the USD price of the trades and launches at or after `from_ts` at the oracle
+/// round active at their block time, those before the first stored round are left as they are.
+///
+/// Returns the number of trades repriced.
+pub async fn reprice_trades(db: &DatabaseConnection, from_ts: i64) -> LibResult<u64> {
+    let sql = r#"
+        UPDATE evt_trade_log t
+        SET price = t.price_token * p.answer
+        FROM (
+            SELECT l.block_number, l.txn_index, l.log_index, (
+                SELECT o.answer FROM oracle_price_log o
+                WHERE o.oracle = r.oracle AND o.updated_at <= l.block_time
+                ORDER BY o.updated_at DESC
+                LIMIT 1
+            ) AS answer
+            FROM evt_trade_log l
+            JOIN raised_token r ON r.address = l.token1
+            WHERE l.block_time >= $1 AND r.oracle <> ''
+        ) p
+        WHERE t.block_number = p.block_number
+            AND t.txn_index = p.txn_index
+            AND t.log_index = p.log_index
+            AND p.answer IS NOT NULL
+    "#;
+    let res = db
+        .execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            sql,
+            [from_ts.into()],
+        ))
+        .await?;
+    let sql = r#"
+        UPDATE evt_token_log t
+        SET init_price_usd = t.init_price * p.answer
+        FROM (
+            SELECT l.block_number, l.txn_index, l.log_index, (
+                SELECT o.answer FROM oracle_price_log o
+                WHERE o.oracle = r.oracle AND o.updated_at <= l.block_time
+                ORDER BY o.updated_at DESC
+                LIMIT 1
+            ) AS answer
+            FROM evt_token_log l
+            JOIN raised_token r ON r.address = l.raised_address
+            WHERE l.block_time >= $1 AND r.oracle <> ''
+        ) p
+        WHERE t.block_number = p.block_number
+            AND t.txn_index = p.txn_index
+            AND t.log_index = p.log_index
+            AND p.answer IS NOT NULL
+    "#;
+    db.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        sql,
+        [from_ts.into()],
+    ))
+    .await?;
+    Ok(res.rows_affected())
+}
+
+/// Record the round of `price` in `oracle_price_log`, returns whether it was new.
+async fn save_round(db: &impl ConnectionTrait, price: &OraclePrice) -> LibResult<bool> {
+    let round_model = db_oracle_price_log::ActiveModel {
+        oracle: Set(price.oracle.clone()),
+        aggregator: Set(format!("{:#x}", price.aggregator)),
+        round_id: Set(price.round as i64),
+        answer: Set(price.price),
+        updated_at: Set(price.updated_at),
+    };
+    let onconflict = OnConflict::columns([
+        db_oracle_price_log::Column::Oracle,
+        db_oracle_price_log::Column::Aggregator,
+        db_oracle_price_log::Column::RoundId,
+    ])
+    .do_nothing()
+    .to_owned();
+    let res = db_oracle_price_log::Entity::insert(round_model)
+        .on_conflict(onconflict)
+        .do_nothing()
+        .exec(db)
+        .await?;
+    Ok(matches!(res, sea_orm::TryInsertResult::Inserted(_)))
+}
+
+/// Store `price` if its round is newer than the stored one, or comes from another
+/// aggregator, and reprice the USD fields of the tokens raised in it.
+///
+/// Returns whether the price was stored.
+async fn save_price(tx: &DatabaseTransaction, price: &OraclePrice) -> LibResult<bool> {
+    let aggregator = format!("{:#x}", price.aggregator);
+    let res = db_raised_token::Entity::update_many()
+        .filter(db_raised_token::Column::Address.eq(&price.asset))
+        .filter(
+            Condition::any()
+                .add(db_raised_token::Column::Aggregator.ne(&aggregator))
+                .add(db_raised_token::Column::PriceRound.lt(price.round as i64)),
+        )
+        .col_expr(db_raised_token::Column::Price, Expr::value(price.price))
+        .col_expr(db_raised_token::Column::Aggregator, Expr::value(aggregator))
+        .col_expr(
+            db_raised_token::Column::PriceRound,
+            Expr::value(price.round as i64),
+        )
+        .col_expr(
+            db_raised_token::Column::PriceTs,
+            Expr::value(price.updated_at),
+        )
+        .exec(tx)
+        .await?;
+    if res.rows_affected == 0 {
+        return Ok(false);
+    }
+    reprice(tx, &price.asset, price.price).await?;
+    Ok(true)
+}
+
+/// Reprice the USD fields of every token raised in `asset`.
+async fn reprice(tx: &DatabaseTransaction, asset: &str, price: Decimal) -> LibResult<()> {
+    db_token_summary::Entity::update_many()
+        .filter(db_token_summary::Column::RaisedToken.eq(asset))
+        .col_expr(
+            db_token_summary::Column::Price,
+            Expr::col(db_token_summary::Column::PriceToken).mul(price),
+        )
+        .col_expr(
+            db_token_summary::Column::MarketCap,
+            Expr::col(db_token_summary::Column::TotalSupply)
+                .mul(Expr::col(db_token_summary::Column::PriceToken))
+                .mul(price),
+        )
+        .col_expr(
+            db_token_summary::Column::Liquidity,
+            Expr::col(db_token_summary::Column::LiquidityToken).mul(price),
+        )
+        .exec(tx)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,427 @@
+use std::collections::{BTreeSet, HashMap};
+use std::str::FromStr;
+
+use alloy::eips::BlockId;
+use alloy::primitives::utils::{format_ether, format_units};
+use alloy::primitives::{Address, Bytes, B256, U256};
+use alloy::providers::Provider;
+use alloy::rpc::client::BatchRequest;
+use alloy::rpc::types::{Filter, Log, RawLog, Transaction};
+use alloy::sol_types::SolEvent;
+use rust_decimal::Decimal;
+use sea_orm::prelude::Expr;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, IntoActiveModel, QueryFilter,
+    QuerySelect, TransactionTrait,
+};
+
+use super::evt_oracle::{price_at, raised_price};
+use super::evt_trade::{is_handled, save_trade};
+use super::Evt;
+use crate::core::consts::{self, PAIR};
+use crate::entity::*;
+use crate::svc::TOKEN;
+use crate::util::{LibError, LibResult};
+
+impl Evt {
+    /// Swap and Sync logs of `[start_block, end_block]` of every graduated token's pair,
+    /// including the pairs of tokens graduated by `factory_logs` in the same range.
+    pub(super) async fn pair_logs(
+        &self,
+        tx: &DatabaseTransaction,
+        factory_logs: &[Log],
+        start_block: u64,
+        end_block: u64,
+    ) -> LibResult<Vec<Log>> {
+        let mut pairs = BTreeSet::new();
+        let graduated = db_token_summary::Entity::find()
+            .filter(db_token_summary::Column::UniswapPool.ne(""))
+            .select_only()
+            .column(db_token_summary::Column::UniswapPool)
+            .into_tuple::<String>()
+            .all(tx)
+            .await?;
+        for pair in graduated {
+            pairs.insert(pair.parse::<Address>()?);
+        }
+        for log in factory_logs
+            .iter()
+            .filter(|v| v.topic0() == Some(&consts::FACTORY::Graduated::SIGNATURE_HASH))
+        {
+            let data = log.log_decode::<consts::FACTORY::Graduated>()?;
+            pairs.insert(data.inner.data.uniswapV2Pair);
+        }
+
+        let pairs: Vec<Address> = pairs.into_iter().collect();
+        let mut logs = Vec::new();
+        for chunk in pairs.chunks(consts::TRANSFER_ADDRESS_BATCH) {
+            let filter = Filter::new().address(chunk.to_vec()).events(vec![
+                "Swap(address,uint256,uint256,uint256,uint256,address)",
+                "Sync(uint112,uint112)",
+            ]);
+            logs.extend(self.get_logs(&filter, start_block, end_block).await?);
+        }
+        Ok(logs)
+    }
+
+    /// Index the Swap and Sync logs of the graduated pairs in `[from_block, cursor]`.
+    ///
+    /// Fills the trades of tokens that graduated before pair trades were indexed, logs that
+    /// were handled already are skipped.
+    pub async fn backfill_pairs(&self, from_block: u64) -> LibResult<()> {
+        let end_block = self.get_block().await?;
+        let mut start_block = from_block;
+        while start_block <= end_block {
+            let latest_block = end_block.min(start_block + consts::MAX_BLOCK_RANGE - 1);
+            tracing::info!("backfill pairs. start block: {start_block}, end block: {latest_block}");
+            let tx = self.store.db_pool.begin().await?;
+            let logs = self.pair_logs(&tx, &[], start_block, latest_block).await?;
+            let blocks = self.blocks(&logs, latest_block).await?;
+            let after_commit = self.handle_logs(&tx, &logs, &blocks).await?;
+            tx.commit().await?;
+            after_commit.apply();
+            start_block = latest_block + 1;
+        }
+        Ok(())
+    }
+
+    /// Senders of the transactions of the Swap logs in `logs`, resolved with batched
+    /// `eth_getTransactionByHash` requests.
+    pub(super) async fn swap_senders(&self, logs: &[Log]) -> LibResult<HashMap<B256, Address>> {
+        let txn_hashes: Vec<B256> = logs
+            .iter()
+            .filter(|v| v.topic0() == Some(&PAIR::Swap::SIGNATURE_HASH))
+            .filter_map(|v| v.transaction_hash)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let mut senders = HashMap::with_capacity(txn_hashes.len());
+        for chunk in txn_hashes.chunks(consts::TXN_BATCH_SIZE) {
+            let mut batch = BatchRequest::new(self.provider.client());
+            let mut waiters = Vec::with_capacity(chunk.len());
+            for txn_hash in chunk {
+                let waiter = batch
+                    .add_call::<_, Option<Transaction>>("eth_getTransactionByHash", &(txn_hash,))?;
+                waiters.push((*txn_hash, waiter));
+            }
+            batch.send().await?;
+            for (txn_hash, waiter) in waiters {
+                let txn = waiter.await?.ok_or_else(|| {
+                    LibError::InternalError(format!("txn not found. txn_hash={txn_hash}"))
+                })?;
+                senders.insert(txn_hash, txn.from);
+            }
+        }
+        Ok(senders)
+    }
+
+    /// Record a swap on a graduated token's Uniswap V2 pair as a trade.
+    ///
+    /// The trader is the sender of the transaction, the `to` of the swap is usually a router.
+    /// It is taken from `senders` and only requested when missing, e.g. on retry.
+    /// The price is the pair's price after the swap, taken from the Sync right before it.
+    pub(super) async fn handle_swap(
+        &self,
+        tx: &DatabaseTransaction,
+        raw_log: RawLog,
+        txn_model: db_evt_txn_log::Model,
+        senders: &HashMap<B256, Address>,
+    ) -> LibResult<()> {
+        if is_handled(tx, &txn_model).await? {
+            return Ok(());
+        }
+        let pair = format!("{:#x}", raw_log.address);
+        let data = PAIR::Swap::decode_raw_log(raw_log.topics, raw_log.data.as_ref(), true)?;
+
+        let (token, raised_token) = pair_token(tx, &pair)
+            .await?
+            .ok_or_else(|| LibError::InternalError(format!("pair not graduated. pair={pair}")))?;
+        let decimals = raised_token.decimal as u8;
+        let token_is0 = token.parse::<Address>()? < raised_token.address.parse::<Address>()?;
+
+        let (trade_type, token_amount, asset_amount) = swap_amounts(
+            token_is0,
+            [
+                data.amount0In,
+                data.amount1In,
+                data.amount0Out,
+                data.amount1Out,
+            ],
+        );
+        let amount0 = Decimal::from_str(&format_ether(token_amount))?;
+        let amount1 = Decimal::from_str(&format_units(asset_amount, decimals)?)?;
+        let price_value = match sync_reserves(tx, &txn_model).await? {
+            Some(reserves) => reserve_price(token_is0, reserves, decimals)?,
+            None => None,
+        };
+        let price_value = price_value
+            .or_else(|| (!amount0.is_zero()).then(|| amount1 / amount0))
+            .unwrap_or_default();
+
+        let txn_hash = B256::from_str(&txn_model.txn_hash)?;
+        let user = match senders.get(&txn_hash) {
+            Some(user) => *user,
+            None => {
+                self.provider
+                    .get_transaction_by_hash(txn_hash)
+                    .await?
+                    .ok_or_else(|| {
+                        LibError::InternalError(format!("txn not found. txn_hash={txn_hash}"))
+                    })?
+                    .from
+            }
+        };
+
+        let oracle_price = price_at(
+            tx,
+            &raised_token.address,
+            txn_model.block_time,
+            txn_model.block_number as u64,
+        )
+        .await?;
+        let trade_log_model = db_evt_trade_log::Model {
+            block_number: txn_model.block_number,
+            txn_index: txn_model.txn_index,
+            log_index: txn_model.log_index,
+            block_time: txn_model.block_time,
+            txn_hash: txn_model.txn_hash.clone(),
+            token_address: token.clone(),
+            user_address: format!("{user:#x}"),
+            trade_type,
+            token0: token,
+            amount0,
+            token1: raised_token.address,
+            amount1,
+            price: price_value * oracle_price,
+            price_token: price_value,
+            is_final: false,
+            amount1_with_fee: None,
+            is_grad_buy: false,
+            venue: consts::VENUE_UNISWAP_V2.to_string(),
+        };
+        save_trade(tx, trade_log_model, txn_model).await
+    }
+}
+
+/// Record a reserve update of a graduated pair and reprice its token from the reserves,
+/// the following Swap of the same transaction takes its price from it too.
+///
+/// The Sync of the liquidity added at graduation comes before the Graduated log, its pair is
+/// not known yet and the reserves are read by the graduation handler instead.
+pub async fn handle_sync(
+    tx: &DatabaseTransaction,
+    raw_log: RawLog,
+    txn_model: db_evt_txn_log::Model,
+) -> LibResult<()> {
+    if is_handled(tx, &txn_model).await? {
+        return Ok(());
+    }
+    let pair = format!("{:#x}", raw_log.address);
+    let data = PAIR::Sync::decode_raw_log(raw_log.topics, raw_log.data.as_ref(), true)?;
+    if let Some((token, raised_token)) = pair_token(tx, &pair).await? {
+        let reserves = (U256::from(data.reserve0), U256::from(data.reserve1));
+        update_pair_summary(tx, &token, &raised_token, reserves).await?;
+    }
+    txn_model.into_active_model().insert(tx).await?;
+    Ok(())
+}
+
+/// Reprice the token of `pair` from the reserves `getReserves` returns at `block`.
+pub async fn sync_pair_summary(
+    tx: &DatabaseTransaction,
+    pair: &str,
+    block: Option<BlockId>,
+) -> LibResult<()> {
+    let (token, raised_token) = pair_token(tx, pair)
+        .await?
+        .ok_or_else(|| LibError::InternalError(format!("pair not graduated. pair={pair}")))?;
+    let reserves = TOKEN.pair_reserves(pair, block).await?;
+    update_pair_summary(tx, &token, &raised_token, reserves).await
+}
+
+/// Set the price and the two-sided liquidity of a graduated token from its pair reserves.
+///
+/// `liquidity_token` is the value of both sides of the pool in the raised asset, `liquidity`
+/// the same in USD at the raised token's oracle price.
+async fn update_pair_summary(
+    tx: &DatabaseTransaction,
+    token: &str,
+    raised_token: &db_raised_token::Model,
+    reserves: (U256, U256),
+) -> LibResult<()> {
+    let token_is0 = token.parse::<Address>()? < raised_token.address.parse::<Address>()?;
+    let (reserve_token, reserve_asset) =
+        pool_reserves(token_is0, reserves, raised_token.decimal as u8)?;
+    if reserve_token.is_zero() {
+        return Ok(());
+    }
+    let price_token = reserve_asset / reserve_token;
+    let oracle_price = raised_price(tx, &raised_token.address).await?;
+    let price = price_token * oracle_price;
+    // both sides are worth the same at the pool price
+    let liquidity_token = reserve_asset + reserve_token * price_token;
+
+    db_token_summary::Entity::update_many()
+        .filter(db_token_summary::Column::TokenAddress.eq(token))
+        .col_expr(db_token_summary::Column::Price, Expr::value(price))
+        .col_expr(
+            db_token_summary::Column::PriceToken,
+            Expr::value(price_token),
+        )
+        .col_expr(
+            db_token_summary::Column::LiquidityToken,
+            Expr::value(liquidity_token),
+        )
+        .col_expr(
+            db_token_summary::Column::Liquidity,
+            Expr::value(liquidity_token * oracle_price),
+        )
+        .col_expr(
+            db_token_summary::Column::MarketCap,
+            Expr::col(db_token_summary::Column::TotalSupply).mul(price),
+        )
+        .exec(tx)
+        .await?;
+    Ok(())
+}
+
+/// The graduated token traded on `pair` and its raised token.
+async fn pair_token(
+    tx: &DatabaseTransaction,
+    pair: &str,
+) -> LibResult<Option<(String, db_raised_token::Model)>> {
+    let summary = db_token_summary::Entity::find()
+        .filter(db_token_summary::Column::UniswapPool.eq(pair))
+        .find_also_related(db_raised_token::Entity)
+        .one(tx)
+        .await?;
+    let Some((summary, raised_token)) = summary else {
+        return Ok(None);
+    };
+    let raised_token = raised_token.ok_or_else(|| {
+        LibError::InternalError(format!(
+            "asset info not found. token={}",
+            summary.token_address
+        ))
+    })?;
+    if raised_token.oracle.is_empty() {
+        return Err(LibError::InternalError(format!(
+            "asset oracle missing. asset={}",
+            raised_token.address
+        )));
+    }
+    Ok(Some((summary.token_address, raised_token)))
+}
+
+/// Reserves of the Sync a pair emits right before `txn_model`'s Swap.
+async fn sync_reserves(
+    tx: &DatabaseTransaction,
+    txn_model: &db_evt_txn_log::Model,
+) -> LibResult<Option<(U256, U256)>> {
+    let sync_model = db_evt_txn_log::Entity::find_by_id((
+        txn_model.block_number,
+        txn_model.txn_index,
+        txn_model.log_index - 1,
+    ))
+    .one(tx)
+    .await?
+    .filter(|v| {
+        v.address == txn_model.address && v.topic_0 == format!("{:#x}", PAIR::Sync::SIGNATURE_HASH)
+    });
+    let Some(sync_model) = sync_model else {
+        return Ok(None);
+    };
+    let data = Bytes::from_str(sync_model.data.as_deref().unwrap_or_default())?;
+    let sync = PAIR::Sync::decode_raw_log([PAIR::Sync::SIGNATURE_HASH], data.as_ref(), true)?;
+    Ok(Some((U256::from(sync.reserve0), U256::from(sync.reserve1))))
+}
+
+/// `(token reserve, asset reserve)` of a pair in token units, the token has 18 decimals.
+fn pool_reserves(
+    token_is0: bool,
+    (reserve0, reserve1): (U256, U256),
+    decimals: u8,
+) -> LibResult<(Decimal, Decimal)> {
+    let (reserve_token, reserve_asset) = if token_is0 {
+        (reserve0, reserve1)
+    } else {
+        (reserve1, reserve0)
+    };
+    let reserve_token = Decimal::from_str(&format_ether(reserve_token))?;
+    let reserve_asset = Decimal::from_str(&format_units(reserve_asset, decimals)?)?;
+    Ok((reserve_token, reserve_asset))
+}
+
+/// Price of the token in the raised asset from the pair reserves, `None` for an empty pool.
+fn reserve_price(
+    token_is0: bool,
+    reserves: (U256, U256),
+    decimals: u8,
+) -> LibResult<Option<Decimal>> {
+    let (reserve_token, reserve_asset) = pool_reserves(token_is0, reserves, decimals)?;
+    if reserve_token.is_zero() {
+        return Ok(None);
+    }
+    Ok(Some(reserve_asset / reserve_token))
+}
+
+/// `(trade_type, token amount, asset amount)` of a swap, 0 buys and 1 sells the token.
+///
+/// `amounts` are `[amount0In, amount1In, amount0Out, amount1Out]`, token0 of a pair is
+/// the lower of its two addresses.
+fn swap_amounts(token_is0: bool, amounts: [U256; 4]) -> (i32, U256, U256) {
+    let [amount0_in, amount1_in, amount0_out, amount1_out] = amounts;
+    let (token_in, asset_in, token_out, asset_out) = if token_is0 {
+        (amount0_in, amount1_in, amount0_out, amount1_out)
+    } else {
+        (amount1_in, amount0_in, amount1_out, amount0_out)
+    };
+    if token_out > token_in {
+        (0, token_out - token_in, asset_in.saturating_sub(asset_out))
+    } else {
+        (1, token_in - token_out, asset_out.saturating_sub(asset_in))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_swap_amounts() {
+        let n = U256::from;
+        // buy, token is token0: asset in as amount1, token out as amount0
+        assert_eq!(
+            swap_amounts(true, [n(0), n(10), n(500), n(0)]),
+            (0, n(500), n(10))
+        );
+        // buy, token is token1
+        assert_eq!(
+            swap_amounts(false, [n(10), n(0), n(0), n(500)]),
+            (0, n(500), n(10))
+        );
+        // sell, token is token1
+        assert_eq!(
+            swap_amounts(false, [n(0), n(500), n(9), n(0)]),
+            (1, n(500), n(9))
+        );
+    }
+
+    #[test]
+    fn t_reserve_price() {
+        let ether = U256::from(10).pow(U256::from(18));
+        let reserves = (
+            U256::from(1_000_000) * ether,
+            U256::from(50) * U256::from(1_000_000),
+        );
+        // token is token0 with 18 decimals, asset token1 with 6 decimals
+        assert_eq!(
+            reserve_price(true, reserves, 6).unwrap(),
+            Some(Decimal::from_str("0.00005").unwrap())
+        );
+        assert_eq!(
+            reserve_price(false, (U256::ZERO, U256::ZERO), 18).unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use alloy::eips::BlockId;
+use alloy::primitives::{Address, B256};
+use alloy::sol_types::SolEvent;
+use sea_orm::prelude::Expr;
+use sea_orm::{
+    ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter, QueryOrder, QuerySelect,
+};
+
+use super::evt_config::rollback_config;
+use super::evt_kline::rebuild_klines;
+use super::evt_pair::sync_pair_summary;
+use super::evt_transfer::sync_user_summary;
+use super::evt_whitelist::rollback_whitelist;
+use crate::core::consts;
+use crate::entity::*;
+use crate::svc::TOKEN;
+use crate::util::{LibError, LibResult};
+
+/// Roll back everything indexed at or above `fork_block`.
+///
+/// The raw event logs above the fork are deleted, failed logs above it too so that they are
+/// not retried on the canonical chain, and the derived rows are restored from the logs that
+/// are left:
+/// 1. tokens launched after the fork are removed again
+/// 2. graduations after the fork are reverted
+/// 3. kline_5m, kline and token_summary of the traded tokens are rebuilt
+/// 4. user_summary balances are reset to the balance ledger that is left
+/// 5. factory_config versions and raised_token whitelist flags are restored
+pub async fn rollback(tx: &DatabaseTransaction, fork_block: u64) -> LibResult<()> {
+    let fork_block = fork_block as i64;
+
+    let launched: HashSet<String> = db_evt_token_log::Entity::find()
+        .filter(db_evt_token_log::Column::BlockNumber.gte(fork_block))
+        .all(tx)
+        .await?
+        .into_iter()
+        .map(|v| v.token_address)
+        .collect();
+
+    let graduated_topic = format!("{:#x}", consts::FACTORY::Graduated::SIGNATURE_HASH);
+    let graduated = db_evt_txn_log::Entity::find()
+        .filter(db_evt_txn_log::Column::BlockNumber.gte(fork_block))
+        .filter(db_evt_txn_log::Column::Topic0.eq(graduated_topic))
+        .all(tx)
+        .await?
+        .into_iter()
+        .filter_map(|v| v.topic_1)
+        .map(|v| topic_address(&v))
+        .collect::<LibResult<Vec<_>>>()?;
+
+    // token -> time of the earliest reverted trade
+    let mut tokens: HashMap<String, i64> = HashMap::new();
+    let trades = db_evt_trade_log::Entity::find()
+        .filter(db_evt_trade_log::Column::BlockNumber.gte(fork_block))
+        .all(tx)
+        .await?;
+    for trade in trades
+        .iter()
+        .filter(|v| !launched.contains(&v.token_address))
+    {
+        let from_ts = tokens
+            .entry(trade.token_address.clone())
+            .or_insert(trade.block_time);
+        *from_ts = (*from_ts).min(trade.block_time);
+    }
+
+    let balances: HashSet<(String, String)> = db_evt_balance_log::Entity::find()
+        .filter(db_evt_balance_log::Column::BlockNumber.gte(fork_block))
+        .select_only()
+        .column(db_evt_balance_log::Column::UserAddress)
+        .column(db_evt_balance_log::Column::TokenAddress)
+        .into_tuple::<(String, String)>()
+        .all(tx)
+        .await?
+        .into_iter()
+        .filter(|(_, token)| !launched.contains(token))
+        .collect();
+
+    tracing::warn!(
+        "rollback from block {fork_block}. launched: {}, graduated: {}, trades: {}",
+        launched.len(),
+        graduated.len(),
+        trades.len()
+    );
+
+    for token in launched.iter() {
+        db_token_info::Entity::update_many()
+            .filter(db_token_info::Column::TokenAddress.eq(token))
+            .col_expr(db_token_info::Column::TokenAddress, Expr::value(""))
+            .col_expr(db_token_info::Column::LaunchTs, Expr::value(0))
+            .exec(tx)
+            .await?;
+        db_token_summary::Entity::delete_many()
+            .filter(db_token_summary::Column::TokenAddress.eq(token))
+            .exec(tx)
+            .await?;
+        db_kline_5m::Entity::delete_many()
+            .filter(db_kline_5m::Column::TokenAddress.eq(token))
+            .exec(tx)
+            .await?;
+        db_kline::Entity::delete_many()
+            .filter(db_kline::Column::TokenAddress.eq(token))
+            .exec(tx)
+            .await?;
+        db_user_summary::Entity::delete_many()
+            .filter(db_user_summary::Column::TokenAddress.eq(token))
+            .exec(tx)
+            .await?;
+    }
+
+    for token in graduated.iter().filter(|v| !launched.contains(*v)) {
+        let launch_ts = db_evt_token_log::Entity::find()
+            .filter(db_evt_token_log::Column::TokenAddress.eq(token))
+            .select_only()
+            .column(db_evt_token_log::Column::BlockTime)
+            .into_tuple::<i64>()
+            .one(tx)
+            .await?
+            .unwrap_or_default();
+        db_token_info::Entity::update_many()
+            .filter(db_token_info::Column::TokenAddress.eq(token))
+            .col_expr(db_token_info::Column::IsLaunched, Expr::value(false))
+            .col_expr(db_token_info::Column::LaunchTs, Expr::value(launch_ts))
+            .exec(tx)
+            .await?;
+        db_token_summary::Entity::update_many()
+            .filter(db_token_summary::Column::TokenAddress.eq(token))
+            .col_expr(db_token_summary::Column::UniswapPool, Expr::value(""))
+            .exec(tx)
+            .await?;
+    }
+
+    db_evt_trade_log::Entity::delete_many()
+        .filter(db_evt_trade_log::Column::BlockNumber.gte(fork_block))
+        .exec(tx)
+        .await?;
+    db_evt_token_log::Entity::delete_many()
+        .filter(db_evt_token_log::Column::BlockNumber.gte(fork_block))
+        .exec(tx)
+        .await?;
+    db_factory_admin_log::Entity::delete_many()
+        .filter(db_factory_admin_log::Column::BlockNumber.gte(fork_block))
+        .exec(tx)
+        .await?;
+    db_evt_transfer_log::Entity::delete_many()
+        .filter(db_evt_transfer_log::Column::BlockNumber.gte(fork_block))
+        .exec(tx)
+        .await?;
+    db_evt_balance_log::Entity::delete_many()
+        .filter(db_evt_balance_log::Column::BlockNumber.gte(fork_block))
+        .exec(tx)
+        .await?;
+    rollback_whitelist(tx, fork_block).await?;
+    db_evt_txn_log::Entity::delete_many()
+        .filter(db_evt_txn_log::Column::BlockNumber.gte(fork_block))
+        .exec(tx)
+        .await?;
+    db_evt_block_log::Entity::delete_many()
+        .filter(db_evt_block_log::Column::BlockNumber.gte(fork_block))
+        .exec(tx)
+        .await?;
+    db_evt_failed_log::Entity::delete_many()
+        .filter(db_evt_failed_log::Column::BlockNumber.gte(fork_block))
+        .exec(tx)
+        .await?;
+    rollback_config(tx, fork_block).await?;
+
+    for (token, from_ts) in tokens.iter() {
+        rebuild_klines(tx, Some(token), *from_ts, None).await?;
+        restore_token_summary(tx, token, fork_block as u64 - 1).await?;
+    }
+
+    for (user, token) in balances.iter() {
+        sync_user_summary(tx, user, token).await?;
+    }
+
+    Ok(())
+}
+
+/// Reset the price fields of token_summary to the last trade left after a rollback, or after
+/// a trade that arrived late, or to the launch price if no trade is left. Graduated tokens are repriced from
+/// their pair reserves.
+///
+/// Curve and pair state are read at `block`, the last block the index stands at, i.e. the
+/// block before the fork or the cursor, not at the head of the chain.
+pub async fn restore_token_summary(
+    tx: &DatabaseTransaction,
+    token: &str,
+    block: u64,
+) -> LibResult<()> {
+    let last_trade = db_evt_trade_log::Entity::find()
+        .filter(db_evt_trade_log::Column::TokenAddress.eq(token))
+        .order_by_desc(db_evt_trade_log::Column::BlockNumber)
+        .order_by_desc(db_evt_trade_log::Column::TxnIndex)
+        .order_by_desc(db_evt_trade_log::Column::LogIndex)
+        .one(tx)
+        .await?;
+
+    let (price, price_token, last_trade_ts) = match last_trade {
+        Some(trade) => (trade.price, trade.price_token, trade.block_time),
+        None => {
+            let token_log = db_evt_token_log::Entity::find()
+                .filter(db_evt_token_log::Column::TokenAddress.eq(token))
+                .one(tx)
+                .await?
+                .ok_or_else(|| LibError::InternalError("token log not found".to_string()))?;
+            let oracle_price = db_raised_token::Entity::find_by_id(&token_log.raised_address)
+                .one(tx)
+                .await?
+                .map(|v| v.price)
+                .unwrap_or_default();
+            (
+                token_log.init_price * oracle_price,
+                token_log.init_price,
+                token_log.block_time,
+            )
+        }
+    };
+    let uniswap_pool = db_token_summary::Entity::find_by_id(token)
+        .select_only()
+        .column(db_token_summary::Column::UniswapPool)
+        .into_tuple::<String>()
+        .one(tx)
+        .await?
+        .unwrap_or_default();
+    if !uniswap_pool.is_empty() {
+        // graduated, price and liquidity follow the pair reserves
+        db_token_summary::Entity::update_many()
+            .filter(db_token_summary::Column::TokenAddress.eq(token))
+            .col_expr(
+                db_token_summary::Column::LastTradeTs,
+                Expr::value(last_trade_ts),
+            )
+            .exec(tx)
+            .await?;
+        return sync_pair_summary(tx, &uniswap_pool, Some(BlockId::number(block))).await;
+    }
+    let (bonding_curve, liquidity_token) = TOKEN
+        .curve_process(token, Some(BlockId::number(block)))
+        .await?;
+
+    db_token_summary::Entity::update_many()
+        .filter(db_token_summary::Column::TokenAddress.eq(token))
+        .col_expr(db_token_summary::Column::Price, Expr::value(price))
+        .col_expr(
+            db_token_summary::Column::PriceToken,
+            Expr::value(price_token),
+        )
+        .col_expr(
+            db_token_summary::Column::BondingCurve,
+            Expr::value(bonding_curve),
+        )
+        .col_expr(
+            db_token_summary::Column::LiquidityToken,
+            Expr::value(liquidity_token),
+        )
+        .col_expr(
+            db_token_summary::Column::Liquidity,
+            Expr::value(liquidity_token * price),
+        )
+        .col_expr(
+            db_token_summary::Column::MarketCap,
+            Expr::col(db_token_summary::Column::TotalSupply).mul(price),
+        )
+        .col_expr(
+            db_token_summary::Column::LastTradeTs,
+            Expr::value(last_trade_ts),
+        )
+        .exec(tx)
+        .await?;
+
+    Ok(())
+}
+
+/// address stored in an indexed topic, e.g. the token of `Graduated`
+fn topic_address(topic: &str) -> LibResult<String> {
+    let word = B256::from_str(topic)?;
+    Ok(format!("{:#x}", Address::from_word(word)))
+}
@@ -1,65 +1,88 @@
 use std::ops::{Div, Sub};
 use std::str::FromStr;
 
+use alloy::eips::BlockId;
 use alloy::primitives::utils::{format_ether, ParseUnits, Unit};
-use alloy::primitives::U256;
+use alloy::primitives::{Address, U256};
 use rust_decimal::Decimal;
 use sea_orm::prelude::Expr;
 use sea_orm::sea_query::OnConflict;
 use sea_orm::ActiveValue::Set;
 use sea_orm::{
-    ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, IntoActiveModel, QueryFilter,
-    QueryOrder, QuerySelect, TransactionTrait,
+    ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, IntoActiveModel, Iterable,
+    QueryFilter, QuerySelect,
 };
 
-use crate::core::Store;
+use super::evt_config::config_at;
+use super::evt_kline::rebuild_klines;
+use super::evt_oracle::price_at;
+use super::evt_reorg::restore_token_summary;
+use crate::core::consts;
 use crate::entity::*;
-use crate::svc::TOKEN;
+use crate::svc::{close_5m_at, TOKEN};
 use crate::util::PeriodType;
 use crate::util::{LibError, LibResult};
 
 /// Handles a trade event by updating various database tables with trade information.
 ///
-/// This function processes a trade event, updating user summaries, token summaries,
+/// This function processes a trade event, updating token summaries and k-lines,
 /// and creating trade log entries. It handles both buy and sell operations.
+/// User balances are not touched here, they follow the Transfer logs of the trade.
 ///
 /// # Arguments
 ///
-/// * `store` - A reference to the data store
+/// * `tx` - The database transaction the trade is written in
 /// * `user` - The address of the user involved in the trade
 /// * `token` - The address of the token being traded
 /// * `amount_in` - The amount of tokens or currency input for the trade
 /// * `amount_out` - The amount of tokens or currency output from the trade
 /// * `price` - The price at which the trade occurred
 /// * `trade_type` - An integer representing the type of trade (0 for buy, 1 for sell)
+/// * `is_grad_buy` - Whether the trade is the purchase that graduates the token, its `amount_in`
+///   includes the fee
 /// * `txn_model` - The transaction model containing additional transaction information
 ///
 /// # Returns
 ///
 /// Returns a `LibResult<()>` which is `Ok(())` if the operation succeeds, or an error if it fails.
+#[allow(clippy::too_many_arguments)]
 pub async fn handle_trade(
-    store: &Store,
+    tx: &DatabaseTransaction,
     user: String,
     token: String,
     amount_in: U256,
     amount_out: U256,
     price: U256,
     trade_type: i32,
+    is_grad_buy: bool,
     txn_model: db_evt_txn_log::Model,
 ) -> LibResult<()> {
+    if is_handled(tx, &txn_model).await? {
+        return Ok(());
+    }
     // get raised token info
-    let (raised_decimal, raised_address, oracle_address) = db_token_summary::Entity::find()
+    let (raised_decimal, raised_address) = db_token_summary::Entity::find()
         .filter(db_token_summary::Column::TokenAddress.eq(token.clone()))
         .inner_join(db_raised_token::Entity)
         .select_only()
         .column(db_raised_token::Column::Decimal)
         .column(db_raised_token::Column::Address)
-        .column(db_raised_token::Column::Oracle)
-        .into_tuple::<(i32, String, String)>()
-        .one(&store.db_pool)
+        .into_tuple::<(i32, String)>()
+        .one(tx)
         .await?
         .ok_or_else(|| LibError::InternalError("".into()))?;
 
+    // the graduating purchase reports its amount with the buy fee on top
+    let (amount_in, amount_in_with_fee) = if is_grad_buy {
+        let buy_fee = config_at(tx, "buy_fee", "", txn_model.block_number)
+            .await?
+            .ok_or_else(|| LibError::InternalError("buy_fee not found".to_string()))?
+            .parse::<U256>()?;
+        (without_fee(amount_in, buy_fee), Some(amount_in))
+    } else {
+        (amount_in, None)
+    };
+
     // convert amount to decimal
     let unit = Unit::new(raised_decimal as u8).unwrap();
     let (amount0, amount1) = if trade_type == 0 {
@@ -75,15 +98,19 @@ pub async fn handle_trade(
     };
 
     let price_value = Decimal::from_str(&format_ether(price))?;
-    let oracle_price = TOKEN.oracle_price(&oracle_address).await?;
+    let oracle_price = price_at(
+        tx,
+        &raised_address,
+        txn_model.block_time,
+        txn_model.block_number as u64,
+    )
+    .await?;
     let price_usd = price_value * oracle_price;
 
-    let user_balance = TOKEN.balance_of(&token, &user).await?;
-
     //     // 1. insert evt_trade_log
-    //     // 2. update user_summary
-    //     // 3. update token_summary
-    //     // 4. update kline_5m
+    //     // 2. update token_summary
+    //     // 3. update kline_5m
+    //     // user_summary follows the Transfer logs of the trade
     let trade_log_model = db_evt_trade_log::Model {
         block_number: txn_model.block_number,
         txn_index: txn_model.txn_index,
@@ -99,36 +126,105 @@ pub async fn handle_trade(
         amount1,
         price: price_usd,
         price_token: price_value,
+        is_final: false,
+        amount1_with_fee: amount_in_with_fee
+            .map(|v| Decimal::from_str(&ParseUnits::from(v).format_units(unit)))
+            .transpose()?,
+        is_grad_buy,
+        venue: consts::VENUE_CURVE.to_string(),
     };
+    save_trade(tx, trade_log_model, txn_model).await
+}
 
-    let user_summary_model = db_user_summary::ActiveModel {
-        user_address: Set(user.clone()),
-        token_address: Set(token.clone()),
-        amount: Set(user_balance),
-        update_ts: Set(txn_model.block_time),
-    };
+/// Amount before a fee of `fee` basis points was added on top of it.
+fn without_fee(amount_with_fee: U256, fee: U256) -> U256 {
+    let denominator = U256::from(consts::FEE_DENOMINATOR);
+    amount_with_fee - amount_with_fee * fee / (denominator + fee)
+}
 
-    let user_onconflict = OnConflict::columns([
-        db_user_summary::Column::UserAddress,
-        db_user_summary::Column::TokenAddress,
-    ])
-    .update_column(db_user_summary::Column::Amount)
-    .update_column(db_user_summary::Column::UpdateTs)
-    .to_owned();
+/// Write a trade of either venue: the trade log, token_summary, kline_5m and the kline of
+/// every period.
+///
+/// A trade older than the token's last trade, e.g. a failed log that is retried later, would
+/// move the price and the candle close back, the candles and token_summary are rebuilt from
+/// the trade log instead.
+pub async fn save_trade(
+    tx: &DatabaseTransaction,
+    trade_log_model: db_evt_trade_log::Model,
+    txn_model: db_evt_txn_log::Model,
+) -> LibResult<()> {
+    txn_model.into_active_model().insert(tx).await?;
+    let last_trade_ts = db_token_summary::Entity::find_by_id(&trade_log_model.token_address)
+        .select_only()
+        .column(db_token_summary::Column::LastTradeTs)
+        .into_tuple::<i64>()
+        .one(tx)
+        .await?
+        .unwrap_or_default();
+    if trade_log_model.block_time < last_trade_ts {
+        let (token, from_ts) = (
+            trade_log_model.token_address.clone(),
+            trade_log_model.block_time,
+        );
+        tracing::warn!(
+            "trade behind the last trade, rebuild. token={token}, block_time={from_ts}, last_trade_ts={last_trade_ts}"
+        );
+        let block = indexed_block(tx)
+            .await?
+            .max(trade_log_model.block_number as u64);
+        trade_log_model.into_active_model().insert(tx).await?;
+        rebuild_klines(tx, Some(&token), from_ts, None).await?;
+        return restore_token_summary(tx, &token, block).await;
+    }
+    handle_token_summary(tx, &trade_log_model).await?;
+    handle_kline_5m(tx, &trade_log_model, PeriodType::M5).await?;
+    for period in PeriodType::iter() {
+        handle_kline(tx, &trade_log_model, period).await?;
+    }
+    trade_log_model.into_active_model().insert(tx).await?;
 
-    let tx = store.db_pool.begin().await?;
-    txn_model.into_active_model().insert(&tx).await?;
-    handle_token_summary(&tx, &trade_log_model).await?;
-    handle_kline_5m(&tx, &trade_log_model, PeriodType::M5).await?;
-    trade_log_model.into_active_model().insert(&tx).await?;
-    db_user_summary::Entity::insert(user_summary_model)
-        .on_conflict(user_onconflict)
-        .exec(&tx)
-        .await?;
+    Ok(())
+}
 
-    tx.commit().await?;
+/// Block of the indexer cursor of the factory, 0 before the first range is indexed.
+async fn indexed_block(tx: &DatabaseTransaction) -> LibResult<u64> {
+    let contract = format!("{:#x}", consts::FACTORY_CONTRACT_ADDR.parse::<Address>()?);
+    let block = db_indexer_cursor::Entity::find()
+        .filter(db_indexer_cursor::Column::Contract.eq(contract))
+        .select_only()
+        .column_as(db_indexer_cursor::Column::BlockNumber.max(), "block_number")
+        .into_tuple::<Option<i64>>()
+        .one(tx)
+        .await?
+        .flatten()
+        .unwrap_or_default();
+    Ok(block as u64)
+}
 
-    Ok(())
+/// Check whether the log was handled before.
+///
+/// Every handled log is recorded in evt_txn_log under its (block_number, txn_index, log_index),
+/// handlers skip a log that is already there, so replaying a block range is a no-op.
+pub async fn is_handled(
+    tx: &DatabaseTransaction,
+    txn_model: &db_evt_txn_log::Model,
+) -> LibResult<bool> {
+    let handled = db_evt_txn_log::Entity::find_by_id((
+        txn_model.block_number,
+        txn_model.txn_index,
+        txn_model.log_index,
+    ))
+    .one(tx)
+    .await?
+    .is_some();
+    if handled {
+        tracing::info!(
+            "evt already handled. txn_hash={}, log_index={}",
+            txn_model.txn_hash,
+            txn_model.log_index
+        );
+    }
+    Ok(handled)
 }
 
 /// Update the token summary in the database based on the latest trade log
@@ -152,14 +248,6 @@ async fn handle_token_summary(
     let start_ts = now_ts - now_ts % 300;
     let end_ts = start_ts - 3600 * 24;
 
-    let last_kline = db_kline_5m::Entity::find()
-        .filter(db_kline_5m::Column::TokenAddress.eq(&exchange.token_address))
-        .filter(db_kline_5m::Column::OpenTs.lte(end_ts))
-        .order_by_desc(db_kline_5m::Column::OpenTs)
-        .limit(1)
-        .one(tx)
-        .await?;
-
     let volume_24h: Decimal = db_kline_5m::Entity::find()
         .filter(db_kline_5m::Column::TokenAddress.eq(&exchange.token_address))
         .filter(db_kline_5m::Column::OpenTs.gte(end_ts))
@@ -172,14 +260,10 @@ async fn handle_token_summary(
         .unwrap_or(Decimal::ZERO)
         + exchange.amount0 * exchange.price_token;
 
-    let last_price = if let Some(kline) = last_kline {
-        kline.close
-    } else {
-        db_kline_5m::Entity::find()
-            .filter(db_kline_5m::Column::TokenAddress.eq(&exchange.token_address))
-            .order_by_asc(db_kline_5m::Column::OpenTs)
-            .limit(1).one(tx).await?.unwrap().close
-    };
+    // close of the gap-filled series 24h ago, the launch price for younger tokens
+    let last_price = close_5m_at(tx, &exchange.token_address, end_ts)
+        .await?
+        .unwrap_or(exchange.price_token);
 
     let last_price = if last_price == Decimal::ZERO {
         Decimal::new(1, 18)
@@ -189,10 +273,27 @@ async fn handle_token_summary(
 
     let rate_24h = exchange.price_token.sub(last_price).div(last_price);
     tracing::info!("last_price: {}, now_price: {}, rate: {}", last_price, exchange.price_token, rate_24h);
-    let (bonding_curve, liquidity_token) = TOKEN.curve_process(&exchange.token_address).await?;
-    let liquidity = liquidity_token * exchange.price;
+    let mut update = db_token_summary::Entity::update_many();
+    // the curve is closed after graduation, its progress and liquidity stay as they were
+    if exchange.venue == consts::VENUE_CURVE {
+        let block = Some(BlockId::number(exchange.block_number as u64));
+        let (bonding_curve, liquidity_token) = TOKEN
+            .curve_process(&exchange.token_address, block)
+            .await?;
+        let liquidity = liquidity_token * exchange.price;
+        update = update
+            .col_expr(
+                db_token_summary::Column::BondingCurve,
+                Expr::value(bonding_curve),
+            )
+            .col_expr(
+                db_token_summary::Column::LiquidityToken,
+                Expr::value(liquidity_token),
+            )
+            .col_expr(db_token_summary::Column::Liquidity, Expr::value(liquidity));
+    }
 
-    db_token_summary::Entity::update_many()
+    update
         .filter(db_token_summary::Column::TokenAddress.eq(&exchange.token_address))
         .col_expr(db_token_summary::Column::Volume24h, Expr::value(volume_24h))
         .col_expr(db_token_summary::Column::Price, Expr::value(exchange.price))
@@ -204,15 +305,6 @@ async fn handle_token_summary(
             db_token_summary::Column::PriceRate24h,
             Expr::value(rate_24h),
         )
-        .col_expr(
-            db_token_summary::Column::BondingCurve,
-            Expr::value(bonding_curve),
-        )
-        .col_expr(
-            db_token_summary::Column::LiquidityToken,
-            Expr::value(liquidity_token),
-        )
-        .col_expr(db_token_summary::Column::Liquidity, Expr::value(liquidity))
         .col_expr(
             db_token_summary::Column::MarketCap,
             Expr::col(db_token_summary::Column::TotalSupply).mul(exchange.price),
@@ -250,6 +342,11 @@ async fn handle_kline_5m(
         volume: Set(exchange.amount0),
         amount: Set(exchange.amount0 * exchange.price_token),
         txn_num: Set(1),
+        open_usd: Set(exchange.price),
+        high_usd: Set(exchange.price),
+        low_usd: Set(exchange.price),
+        close_usd: Set(exchange.price),
+        amount_usd: Set(exchange.amount0 * exchange.price),
     };
 
     let conflict = OnConflict::columns([
@@ -257,6 +354,7 @@ async fn handle_kline_5m(
         db_kline_5m::Column::OpenTs,
     ])
     .update_column(db_kline_5m::Column::Close)
+    .update_column(db_kline_5m::Column::CloseUsd)
     .value(
         db_kline_5m::Column::High,
         Expr::case(
@@ -283,6 +381,33 @@ async fn handle_kline_5m(
         Expr::col((db_kline_5m::Entity, db_kline_5m::Column::Amount))
             .add(Expr::val(exchange.amount0 * exchange.price_token)),
     )
+    .value(
+        db_kline_5m::Column::HighUsd,
+        Expr::case(
+            Expr::col((db_kline_5m::Entity, db_kline_5m::Column::HighUsd)).lt(exchange.price),
+            Expr::val(exchange.price),
+        )
+        .finally(Expr::col((
+            db_kline_5m::Entity,
+            db_kline_5m::Column::HighUsd,
+        ))),
+    )
+    .value(
+        db_kline_5m::Column::LowUsd,
+        Expr::case(
+            Expr::col((db_kline_5m::Entity, db_kline_5m::Column::LowUsd)).gt(exchange.price),
+            Expr::val(exchange.price),
+        )
+        .finally(Expr::col((
+            db_kline_5m::Entity,
+            db_kline_5m::Column::LowUsd,
+        ))),
+    )
+    .value(
+        db_kline_5m::Column::AmountUsd,
+        Expr::col((db_kline_5m::Entity, db_kline_5m::Column::AmountUsd))
+            .add(Expr::val(exchange.amount0 * exchange.price)),
+    )
     .value(
         db_kline_5m::Column::TxnNum,
         Expr::col((db_kline_5m::Entity, db_kline_5m::Column::TxnNum)).add(Expr::val(1)),
@@ -296,3 +421,116 @@ async fn handle_kline_5m(
 
     Ok(())
 }
+
+/// Add a trade to the `period` candle of `kline`.
+async fn handle_kline(
+    tx: &DatabaseTransaction,
+    exchange: &db_evt_trade_log::Model,
+    period: PeriodType,
+) -> LibResult<()> {
+    let open_ts = period.open_ts(exchange.block_time);
+    let close_ts = period.close_ts(open_ts);
+
+    let kline = db_kline::ActiveModel {
+        token_address: Set(exchange.token_address.clone()),
+        period: Set(period.name().to_string()),
+        open_ts: Set(open_ts),
+        close_ts: Set(close_ts),
+        high: Set(exchange.price_token),
+        low: Set(exchange.price_token),
+        open: Set(exchange.price_token),
+        close: Set(exchange.price_token),
+        volume: Set(exchange.amount0),
+        amount: Set(exchange.amount0 * exchange.price_token),
+        txn_num: Set(1),
+        open_usd: Set(exchange.price),
+        high_usd: Set(exchange.price),
+        low_usd: Set(exchange.price),
+        close_usd: Set(exchange.price),
+        amount_usd: Set(exchange.amount0 * exchange.price),
+    };
+
+    let conflict = OnConflict::columns([
+        db_kline::Column::TokenAddress,
+        db_kline::Column::Period,
+        db_kline::Column::OpenTs,
+    ])
+    .update_column(db_kline::Column::Close)
+    .update_column(db_kline::Column::CloseUsd)
+    .value(
+        db_kline::Column::High,
+        Expr::case(
+            Expr::col((db_kline::Entity, db_kline::Column::High)).lt(exchange.price_token),
+            Expr::val(exchange.price_token),
+        )
+        .finally(Expr::col((db_kline::Entity, db_kline::Column::High))),
+    )
+    .value(
+        db_kline::Column::Low,
+        Expr::case(
+            Expr::col((db_kline::Entity, db_kline::Column::Low)).gt(exchange.price_token),
+            Expr::val(exchange.price_token),
+        )
+        .finally(Expr::col((db_kline::Entity, db_kline::Column::Low))),
+    )
+    .value(
+        db_kline::Column::Volume,
+        Expr::col((db_kline::Entity, db_kline::Column::Volume)).add(Expr::val(exchange.amount0)),
+    )
+    .value(
+        db_kline::Column::Amount,
+        Expr::col((db_kline::Entity, db_kline::Column::Amount))
+            .add(Expr::val(exchange.amount0 * exchange.price_token)),
+    )
+    .value(
+        db_kline::Column::HighUsd,
+        Expr::case(
+            Expr::col((db_kline::Entity, db_kline::Column::HighUsd)).lt(exchange.price),
+            Expr::val(exchange.price),
+        )
+        .finally(Expr::col((db_kline::Entity, db_kline::Column::HighUsd))),
+    )
+    .value(
+        db_kline::Column::LowUsd,
+        Expr::case(
+            Expr::col((db_kline::Entity, db_kline::Column::LowUsd)).gt(exchange.price),
+            Expr::val(exchange.price),
+        )
+        .finally(Expr::col((db_kline::Entity, db_kline::Column::LowUsd))),
+    )
+    .value(
+        db_kline::Column::AmountUsd,
+        Expr::col((db_kline::Entity, db_kline::Column::AmountUsd))
+            .add(Expr::val(exchange.amount0 * exchange.price)),
+    )
+    .value(
+        db_kline::Column::TxnNum,
+        Expr::col((db_kline::Entity, db_kline::Column::TxnNum)).add(Expr::val(1)),
+    )
+    .to_owned();
+
+    db_kline::Entity::insert(kline)
+        .on_conflict(conflict)
+        .exec(tx)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_without_fee() {
+        // 1% on top of 1000
+        assert_eq!(
+            without_fee(U256::from(1010), U256::from(100)),
+            U256::from(1000)
+        );
+        let amount = U256::from(2_500_000_000_000_000_000u128);
+        let with_fee = amount + amount * U256::from(150) / U256::from(10000);
+        assert_eq!(without_fee(with_fee, U256::from(150)), amount);
+        assert_eq!(without_fee(with_fee, U256::ZERO), with_fee);
+    }
+}
@@ -0,0 +1,486 @@
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+use alloy::eips::BlockId;
+use alloy::primitives::utils::format_ether;
+use alloy::primitives::Address;
+use alloy::rpc::types::{Filter, Log, RawLog};
+use alloy::sol_types::SolEvent;
+use rust_decimal::Decimal;
+use sea_orm::prelude::Expr;
+use sea_orm::sea_query::{OnConflict, SimpleExpr};
+use sea_orm::ActiveValue::Set;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseTransaction, EntityTrait,
+    IntoActiveModel, QueryFilter, QueryOrder, QuerySelect,
+};
+use sea_orm::{Order, TransactionTrait};
+
+use super::evt_trade::is_handled;
+use super::Evt;
+use crate::core::consts;
+use crate::entity::*;
+use crate::svc::TOKEN;
+use crate::util::LibResult;
+
+/// `indexer_state` key written once `transfer backfill` completed the balance ledger
+const LEDGER_COMPLETE: &str = "transfer_ledger";
+
+impl Evt {
+    /// Transfer logs of `[start_block, end_block]` of every token launched by the factory,
+    /// including the tokens launched by `factory_logs` in the same range.
+    pub(super) async fn transfer_logs(
+        &self,
+        tx: &DatabaseTransaction,
+        factory_logs: &[Log],
+        start_block: u64,
+        end_block: u64,
+    ) -> LibResult<Vec<Log>> {
+        let mut tokens = BTreeSet::new();
+        let launched = db_evt_token_log::Entity::find()
+            .select_only()
+            .column(db_evt_token_log::Column::TokenAddress)
+            .into_tuple::<String>()
+            .all(tx)
+            .await?;
+        for token in launched {
+            tokens.insert(token.parse::<Address>()?);
+        }
+        for log in factory_logs
+            .iter()
+            .filter(|v| v.topic0() == Some(&consts::FACTORY::Launched::SIGNATURE_HASH))
+        {
+            let data = log.log_decode::<consts::FACTORY::Launched>()?;
+            tokens.insert(data.inner.data.token);
+        }
+
+        let tokens: Vec<Address> = tokens.into_iter().collect();
+        let mut logs = Vec::new();
+        for chunk in tokens.chunks(consts::TRANSFER_ADDRESS_BATCH) {
+            let filter = Filter::new()
+                .address(chunk.to_vec())
+                .event("Transfer(address,address,uint256)");
+            logs.extend(self.get_logs(&filter, start_block, end_block).await?);
+        }
+        Ok(logs)
+    }
+
+    /// Index the Transfer logs of `[from_block, cursor]`.
+    ///
+    /// Fills the balance ledger for transfers that happened before it existed, logs that
+    /// were handled already are skipped.
+    pub async fn backfill_transfers(&self, from_block: u64) -> LibResult<()> {
+        let end_block = self.get_block().await?;
+        let mut start_block = from_block;
+        while start_block <= end_block {
+            let latest_block = end_block.min(start_block + consts::MAX_BLOCK_RANGE - 1);
+            tracing::info!(
+                "backfill transfers. start block: {start_block}, end block: {latest_block}"
+            );
+            let tx = self.store.db_pool.begin().await?;
+            let logs = self
+                .transfer_logs(&tx, &[], start_block, latest_block)
+                .await?;
+            let blocks = self.blocks(&logs, latest_block).await?;
+            let after_commit = self.handle_logs(&tx, &logs, &blocks).await?;
+            tx.commit().await?;
+            after_commit.apply();
+            start_block = latest_block + 1;
+        }
+
+        // the ledger is complete, user_summary follows it from now on
+        let tx = self.store.db_pool.begin().await?;
+        let state = db_indexer_state::ActiveModel {
+            key: Set(LEDGER_COMPLETE.to_string()),
+            value: Set(from_block.to_string()),
+            update_ts: Set(chrono::Utc::now().timestamp()),
+        };
+        let onconflict = OnConflict::column(db_indexer_state::Column::Key)
+            .update_column(db_indexer_state::Column::Value)
+            .update_column(db_indexer_state::Column::UpdateTs)
+            .to_owned();
+        db_indexer_state::Entity::insert(state)
+            .on_conflict(onconflict)
+            .exec(&tx)
+            .await?;
+        let holders = db_evt_balance_log::Entity::find()
+            .select_only()
+            .column(db_evt_balance_log::Column::UserAddress)
+            .column(db_evt_balance_log::Column::TokenAddress)
+            .distinct()
+            .into_tuple::<(String, String)>()
+            .all(&tx)
+            .await?;
+        for (user, token) in holders.iter() {
+            sync_user_summary(&tx, user, token).await?;
+        }
+        tx.commit().await?;
+        tracing::info!("balance ledger complete. holders: {}", holders.len());
+        Ok(())
+    }
+
+    /// Spot-check the served balances against the chain.
+    ///
+    /// A random sample of `user_summary` rows is compared with `balanceOf` at the cursor
+    /// block, and so is the running balance of the ledger once it is complete. Every
+    /// difference is logged as an error.
+    pub async fn check_balances(&self) -> LibResult<()> {
+        let Some(block_number) = self.cursor_block().await? else {
+            return Ok(());
+        };
+        let block = Some(BlockId::number(block_number));
+        let complete = ledger_complete(&self.store.db_pool).await?;
+        let samples = db_user_summary::Entity::find()
+            .order_by(Expr::cust("RANDOM()"), Order::Asc)
+            .limit(consts::BALANCE_CHECK_SIZE)
+            .all(&self.store.db_pool)
+            .await?;
+        for sample in samples {
+            let chain = TOKEN
+                .balance_of(&sample.token_address, &sample.user_address, block)
+                .await?;
+            if sample.amount != chain {
+                tracing::error!(
+                    "balance mismatch. user={}, token={}, block={block_number}, user_summary={}, chain={chain}",
+                    sample.user_address,
+                    sample.token_address,
+                    sample.amount
+                );
+            }
+            // running balances start at zero until the ledger is complete
+            if !complete {
+                continue;
+            }
+            let ledger = db_evt_balance_log::Entity::find()
+                .filter(db_evt_balance_log::Column::UserAddress.eq(&sample.user_address))
+                .filter(db_evt_balance_log::Column::TokenAddress.eq(&sample.token_address))
+                .filter(db_evt_balance_log::Column::BlockNumber.lte(block_number as i64))
+                .order_by_desc(db_evt_balance_log::Column::BlockNumber)
+                .order_by_desc(db_evt_balance_log::Column::TxnIndex)
+                .order_by_desc(db_evt_balance_log::Column::LogIndex)
+                .select_only()
+                .column(db_evt_balance_log::Column::TotalAmount)
+                .into_tuple::<Decimal>()
+                .one(&self.store.db_pool)
+                .await?
+                .unwrap_or_default();
+            if ledger != chain {
+                tracing::error!(
+                    "balance mismatch. user={}, token={}, block={block_number}, ledger={ledger}, chain={chain}",
+                    sample.user_address,
+                    sample.token_address
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Record a token transfer and the balance change of both sides, mints and burns
+/// only change the balance of the receiver or the sender.
+pub async fn handle_transfer(
+    tx: &DatabaseTransaction,
+    raw_log: RawLog,
+    txn_model: db_evt_txn_log::Model,
+) -> LibResult<()> {
+    if is_handled(tx, &txn_model).await? {
+        return Ok(());
+    }
+    let token = format!("{:#x}", raw_log.address);
+    let data =
+        consts::ERC20::Transfer::decode_raw_log(raw_log.topics, raw_log.data.as_ref(), true)?;
+    let (from, to) = (format!("{:#x}", data.from), format!("{:#x}", data.to));
+    let amount = Decimal::from_str(&format_ether(data.value))?;
+
+    let transfer_model = db_evt_transfer_log::ActiveModel {
+        block_number: Set(txn_model.block_number),
+        txn_index: Set(txn_model.txn_index),
+        log_index: Set(txn_model.log_index),
+        block_time: Set(txn_model.block_time),
+        txn_hash: Set(txn_model.txn_hash.clone()),
+        token_address: Set(token.clone()),
+        from_address: Set(from.clone()),
+        to_address: Set(to.clone()),
+        amount: Set(amount),
+    };
+    transfer_model.insert(tx).await?;
+    if data.from != data.to {
+        if data.from != Address::ZERO {
+            update_balance(tx, &from, &token, -amount, &txn_model).await?;
+        }
+        if data.to != Address::ZERO {
+            update_balance(tx, &to, &token, amount, &txn_model).await?;
+        }
+    }
+    txn_model.into_active_model().insert(tx).await?;
+
+    Ok(())
+}
+
+/// Insert a balance change of `user` and shift the running totals of the changes after it,
+/// so a transfer that is handled late, e.g. by a retry, still adds up in chain order.
+async fn update_balance(
+    tx: &DatabaseTransaction,
+    user: &str,
+    token: &str,
+    delta: Decimal,
+    txn_model: &db_evt_txn_log::Model,
+) -> LibResult<()> {
+    let prev_total = db_evt_balance_log::Entity::find()
+        .filter(db_evt_balance_log::Column::UserAddress.eq(user))
+        .filter(db_evt_balance_log::Column::TokenAddress.eq(token))
+        .filter(balance_position().lt(log_position(txn_model)))
+        .order_by_desc(db_evt_balance_log::Column::BlockNumber)
+        .order_by_desc(db_evt_balance_log::Column::TxnIndex)
+        .order_by_desc(db_evt_balance_log::Column::LogIndex)
+        .select_only()
+        .column(db_evt_balance_log::Column::TotalAmount)
+        .into_tuple::<Decimal>()
+        .one(tx)
+        .await?
+        .unwrap_or_default();
+
+    let balance_model = db_evt_balance_log::ActiveModel {
+        block_number: Set(txn_model.block_number),
+        txn_index: Set(txn_model.txn_index),
+        log_index: Set(txn_model.log_index),
+        user_address: Set(user.to_string()),
+        token_address: Set(token.to_string()),
+        block_time: Set(txn_model.block_time),
+        txn_hash: Set(txn_model.txn_hash.clone()),
+        delta_amount: Set(delta),
+        total_amount: Set(prev_total + delta),
+    };
+    balance_model.insert(tx).await?;
+    db_evt_balance_log::Entity::update_many()
+        .filter(db_evt_balance_log::Column::UserAddress.eq(user))
+        .filter(db_evt_balance_log::Column::TokenAddress.eq(token))
+        .filter(balance_position().gt(log_position(txn_model)))
+        .col_expr(
+            db_evt_balance_log::Column::TotalAmount,
+            Expr::col(db_evt_balance_log::Column::TotalAmount).add(delta),
+        )
+        .exec(tx)
+        .await?;
+    sync_user_summary(tx, user, token).await
+}
+
+/// Whether `transfer backfill` has filled the balance ledger with the transfers from before
+/// it existed.
+async fn ledger_complete(db: &impl ConnectionTrait) -> LibResult<bool> {
+    let state = db_indexer_state::Entity::find_by_id(LEDGER_COMPLETE)
+        .one(db)
+        .await?;
+    Ok(state.is_some())
+}
+
+/// Set `user_summary.amount` to the latest running balance of the ledger.
+///
+/// Until the ledger is complete its running balances start at zero, the balance is read
+/// with `balanceOf` at the block of the latest ledger change instead, and a row that is newer
+/// than that change or has no ledger change is left as it is.
+///
+/// Zero balances and the balances of the factory and of the token's pair have no row.
+pub async fn sync_user_summary(tx: &DatabaseTransaction, user: &str, token: &str) -> LibResult<()> {
+    let complete = ledger_complete(tx).await?;
+    let latest = db_evt_balance_log::Entity::find()
+        .filter(db_evt_balance_log::Column::UserAddress.eq(user))
+        .filter(db_evt_balance_log::Column::TokenAddress.eq(token))
+        .order_by_desc(db_evt_balance_log::Column::BlockNumber)
+        .order_by_desc(db_evt_balance_log::Column::TxnIndex)
+        .order_by_desc(db_evt_balance_log::Column::LogIndex)
+        .one(tx)
+        .await?;
+    let balance = match latest {
+        Some(latest) if complete => Some((latest.total_amount, latest.block_time)),
+        Some(latest) => {
+            // a backfilled change older than the row does not move it back
+            let update_ts =
+                db_user_summary::Entity::find_by_id((user.to_string(), token.to_string()))
+                    .select_only()
+                    .column(db_user_summary::Column::UpdateTs)
+                    .into_tuple::<i64>()
+                    .one(tx)
+                    .await?;
+            if update_ts.is_some_and(|v| v > latest.block_time) {
+                return Ok(());
+            }
+            let block = Some(BlockId::number(latest.block_number as u64));
+            let amount = TOKEN.balance_of(token, user, block).await?;
+            Some((amount, latest.block_time))
+        }
+        None if complete => None,
+        None => return Ok(()),
+    };
+    let balance = match balance {
+        Some((amount, _)) if amount.is_zero() => None,
+        Some(_) if is_pool_holder(tx, user, token).await? => None,
+        v => v,
+    };
+    let Some((amount, update_ts)) = balance else {
+        db_user_summary::Entity::delete_many()
+            .filter(db_user_summary::Column::UserAddress.eq(user))
+            .filter(db_user_summary::Column::TokenAddress.eq(token))
+            .exec(tx)
+            .await?;
+        return Ok(());
+    };
+
+    let user_summary_model = db_user_summary::ActiveModel {
+        user_address: Set(user.to_string()),
+        token_address: Set(token.to_string()),
+        amount: Set(amount),
+        update_ts: Set(update_ts),
+    };
+    let onconflict = OnConflict::columns([
+        db_user_summary::Column::UserAddress,
+        db_user_summary::Column::TokenAddress,
+    ])
+    .update_column(db_user_summary::Column::Amount)
+    .update_column(db_user_summary::Column::UpdateTs)
+    .to_owned();
+    db_user_summary::Entity::insert(user_summary_model)
+        .on_conflict(onconflict)
+        .exec(tx)
+        .await?;
+    Ok(())
+}
+
+/// Whether `user` is the factory, which holds the curve supply, or the pair of `token`.
+async fn is_pool_holder(tx: &DatabaseTransaction, user: &str, token: &str) -> LibResult<bool> {
+    let factory = consts::FACTORY_CONTRACT_ADDR.parse::<Address>()?;
+    if user.parse::<Address>()? == factory {
+        return Ok(true);
+    }
+    let uniswap_pool = db_token_summary::Entity::find_by_id(token)
+        .select_only()
+        .column(db_token_summary::Column::UniswapPool)
+        .into_tuple::<String>()
+        .one(tx)
+        .await?
+        .unwrap_or_default();
+    Ok(uniswap_pool.eq_ignore_ascii_case(user))
+}
+
+/// `(block_number, txn_index, log_index)` of a balance row, compared as a row value
+fn balance_position() -> Expr {
+    Expr::tuple([
+        Expr::col(db_evt_balance_log::Column::BlockNumber).into(),
+        Expr::col(db_evt_balance_log::Column::TxnIndex).into(),
+        Expr::col(db_evt_balance_log::Column::LogIndex).into(),
+    ])
+}
+
+fn log_position(txn_model: &db_evt_txn_log::Model) -> SimpleExpr {
+    Expr::tuple([
+        Expr::value(txn_model.block_number),
+        Expr::value(txn_model.txn_index),
+        Expr::value(txn_model.log_index),
+    ])
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    #[ignore = "requires postgres"]
+    async fn t_update_balance() {
+        dotenvy::dotenv().ok();
+        let db = sea_orm::Database::connect(consts::PG_URL.as_str())
+            .await
+            .unwrap();
+        // everything is rolled back, tables included
+        let tx = db.begin().await.unwrap();
+        for sql in [
+            include_str!("../../data/sql/003_indexer_cursor.sql"),
+            include_str!("../../data/sql/011_evt_transfer_log.sql"),
+            include_str!("../../data/sql/012_indexer_state.sql"),
+            // the columns of the baseline tables that update_balance reads
+            "CREATE TABLE IF NOT EXISTS user_summary (user_address VARCHAR(42), token_address VARCHAR(42), \
+                amount NUMERIC(78, 18), update_ts BIGINT, PRIMARY KEY (user_address, token_address))",
+            "CREATE TABLE IF NOT EXISTS token_summary (token_address VARCHAR(42) PRIMARY KEY, \
+                uniswap_pool VARCHAR(42) NOT NULL DEFAULT '')",
+        ] {
+            tx.execute_unprepared(sql).await.unwrap();
+        }
+        db_indexer_state::Entity::insert(db_indexer_state::ActiveModel {
+            key: Set(LEDGER_COMPLETE.to_string()),
+            value: Set("0".to_string()),
+            update_ts: Set(0),
+        })
+        .on_conflict(
+            OnConflict::column(db_indexer_state::Column::Key)
+                .do_nothing()
+                .to_owned(),
+        )
+        .do_nothing()
+        .exec(&tx)
+        .await
+        .unwrap();
+
+        let (user, token) = (
+            "0x00000000000000000000000000000000000000a1",
+            "0x00000000000000000000000000000000000000b2",
+        );
+        let changes = [
+            ((100, 0, 1), 50),
+            ((100, 2, 0), -20),
+            ((105, 1, 4), 5),
+            ((107, 0, 0), -35),
+        ];
+        let totals = || async {
+            db_evt_balance_log::Entity::find()
+                .filter(db_evt_balance_log::Column::UserAddress.eq(user))
+                .filter(db_evt_balance_log::Column::TokenAddress.eq(token))
+                .order_by_asc(db_evt_balance_log::Column::BlockNumber)
+                .order_by_asc(db_evt_balance_log::Column::TxnIndex)
+                .order_by_asc(db_evt_balance_log::Column::LogIndex)
+                .select_only()
+                .column(db_evt_balance_log::Column::TotalAmount)
+                .into_tuple::<Decimal>()
+                .all(&tx)
+                .await
+                .unwrap()
+        };
+        let amount = || async {
+            db_user_summary::Entity::find_by_id((user.to_string(), token.to_string()))
+                .one(&tx)
+                .await
+                .unwrap()
+                .map(|v| v.amount)
+        };
+
+        // a retried or backfilled change handled after the later ones adds up in chain order
+        for i in [2, 0, 3, 1] {
+            let ((block_number, txn_index, log_index), delta) = changes[i];
+            let txn_model = db_evt_txn_log::Model {
+                block_number,
+                txn_index,
+                log_index,
+                block_time: block_number * 12,
+                txn_hash: format!("{:#066x}", i),
+                address: token.to_string(),
+                topic_0: String::new(),
+                topic_1: None,
+                topic_2: None,
+                topic_3: None,
+                data: None,
+            };
+            update_balance(&tx, user, token, Decimal::from(delta), &txn_model)
+                .await
+                .unwrap();
+            if i == 0 {
+                // the changes at 100/0/1 and 105/1/4 so far
+                assert_eq!(totals().await, [50, 55].map(Decimal::from));
+                assert_eq!(amount().await, Some(Decimal::from(55)));
+            }
+        }
+        assert_eq!(totals().await, [50, 30, 35, 0].map(Decimal::from));
+        // a zero balance has no row
+        assert_eq!(amount().await, None);
+
+        tx.rollback().await.unwrap();
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+
+use alloy::eips::BlockId;
+use alloy::rpc::types::RawLog;
+use alloy::sol_types::SolEvent;
+use rust_decimal::Decimal;
+use sea_orm::prelude::Expr;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, IntoActiveModel, QueryFilter,
+    QueryOrder,
+};
+
+use super::evt_trade::is_handled;
+use crate::core::consts::FACTORY;
+use crate::entity::*;
+use crate::svc::TOKEN;
+use crate::util::{LibError, LibResult};
+
+/// Keep `raised_token.is_whitelisted` in sync with the factory whitelist.
+///
+/// An asset that is not registered yet is added with name, symbol and decimals read from
+/// the chain. Its oracle has to be set by an operator, until then it is reported as missing.
+pub async fn handle_whitelist(
+    tx: &DatabaseTransaction,
+    raw_log: RawLog,
+    txn_model: db_evt_txn_log::Model,
+) -> LibResult<()> {
+    if is_handled(tx, &txn_model).await? {
+        return Ok(());
+    }
+    let topic = raw_log.topics.first().copied();
+    let (asset, is_whitelisted) = if topic == Some(FACTORY::AssetAddedToWhitelist::SIGNATURE_HASH) {
+        let data = FACTORY::AssetAddedToWhitelist::decode_raw_log(
+            raw_log.topics,
+            raw_log.data.as_ref(),
+            true,
+        )?;
+        (format!("{:#x}", data.asset), true)
+    } else {
+        let data = FACTORY::AssetRemovedFromWhitelist::decode_raw_log(
+            raw_log.topics,
+            raw_log.data.as_ref(),
+            true,
+        )?;
+        (format!("{:#x}", data.asset), false)
+    };
+
+    let raised_token = db_raised_token::Entity::find_by_id(&asset).one(tx).await?;
+    match raised_token {
+        Some(raised_token) => {
+            if is_whitelisted && raised_token.oracle.is_empty() {
+                tracing::warn!("raised token oracle missing. asset={asset}");
+            }
+            set_whitelisted(tx, &asset, is_whitelisted).await?;
+        }
+        None => {
+            let block = Some(BlockId::number(txn_model.block_number as u64));
+            let (name, symbol, decimals) = TOKEN.erc20_info(&asset, block).await?;
+            tracing::warn!(
+                "raised token registered, oracle missing. asset={asset}, symbol={symbol}"
+            );
+            let raised_token_model = db_raised_token::ActiveModel {
+                address: Set(asset.clone()),
+                name: Set(name),
+                symbol: Set(symbol),
+                decimal: Set(decimals as i32),
+                icon: Set("".to_string()),
+                oracle: Set("".to_string()),
+                price: Set(Decimal::ZERO),
+                create_ts: Set(txn_model.block_time),
+                is_whitelisted: Set(is_whitelisted),
+                aggregator: Set("".to_string()),
+                price_round: Set(0),
+                price_ts: Set(0),
+            };
+            raised_token_model.insert(tx).await?;
+        }
+    }
+    txn_model.into_active_model().insert(tx).await?;
+    Ok(())
+}
+
+/// Oracle of a raised token, an error if the asset is unknown or its oracle not set yet.
+pub async fn raised_oracle(tx: &DatabaseTransaction, asset: &str) -> LibResult<String> {
+    let raised_token = db_raised_token::Entity::find_by_id(asset)
+        .one(tx)
+        .await?
+        .ok_or_else(|| LibError::InternalError(format!("asset info not found. asset={asset}")))?;
+    if raised_token.oracle.is_empty() {
+        return Err(LibError::InternalError(format!(
+            "asset oracle missing. asset={asset}"
+        )));
+    }
+    Ok(raised_token.oracle)
+}
+
+/// Revert the whitelist changes at or above `fork_block`, call before their txn logs
+/// are deleted.
+///
+/// An asset gets the state of its last whitelist event below the fork, or, without such
+/// an event, the state before its first reverted event.
+pub async fn rollback_whitelist(tx: &DatabaseTransaction, fork_block: i64) -> LibResult<()> {
+    let topics = [
+        format!("{:#x}", FACTORY::AssetAddedToWhitelist::SIGNATURE_HASH),
+        format!("{:#x}", FACTORY::AssetRemovedFromWhitelist::SIGNATURE_HASH),
+    ];
+    let reverted = db_evt_txn_log::Entity::find()
+        .filter(db_evt_txn_log::Column::BlockNumber.gte(fork_block))
+        .filter(db_evt_txn_log::Column::Topic0.is_in(topics.clone()))
+        .order_by_desc(db_evt_txn_log::Column::BlockNumber)
+        .order_by_desc(db_evt_txn_log::Column::LogIndex)
+        .all(tx)
+        .await?;
+    // asset -> state before the first reverted event, the earliest event is inserted last
+    let mut assets = HashMap::new();
+    for log in reverted {
+        if let Some(asset) = log.topic_1 {
+            assets.insert(asset, log.topic_0 != topics[0]);
+        }
+    }
+
+    for (asset_topic, before) in assets {
+        let last = db_evt_txn_log::Entity::find()
+            .filter(db_evt_txn_log::Column::BlockNumber.lt(fork_block))
+            .filter(db_evt_txn_log::Column::Topic0.is_in(topics.clone()))
+            .filter(db_evt_txn_log::Column::Topic1.eq(&asset_topic))
+            .order_by_desc(db_evt_txn_log::Column::BlockNumber)
+            .order_by_desc(db_evt_txn_log::Column::LogIndex)
+            .one(tx)
+            .await?;
+        let is_whitelisted = match last {
+            Some(log) => log.topic_0 == topics[0],
+            None => before,
+        };
+        let asset = format!("0x{}", &asset_topic[asset_topic.len() - 40..]);
+        set_whitelisted(tx, &asset, is_whitelisted).await?;
+    }
+    Ok(())
+}
+
+async fn set_whitelisted(
+    tx: &DatabaseTransaction,
+    asset: &str,
+    is_whitelisted: bool,
+) -> LibResult<()> {
+    db_raised_token::Entity::update_many()
+        .filter(db_raised_token::Column::Address.eq(asset))
+        .col_expr(
+            db_raised_token::Column::IsWhitelisted,
+            Expr::value(is_whitelisted),
+        )
+        .exec(tx)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,176 @@
+use std::time::{Duration, Instant};
+
+use alloy::network::Ethereum;
+use alloy::primitives::Address;
+use alloy::providers::{Provider, RootProvider};
+use alloy::pubsub::Subscription;
+use alloy::rpc::types::{Filter, Log};
+use tokio::sync::broadcast::error::RecvError;
+
+use super::Evt;
+use crate::core::consts;
+use crate::svc::PRICE;
+use crate::util::{LibError, LibResult};
+
+impl Evt {
+    /// Follow new logs over the websocket provider.
+    ///
+    /// When the socket drops the indexer polls for `WS_RECONNECT_INTERVAL` seconds and
+    /// connects again, the cursor makes sure no block is skipped in between.
+    pub(super) async fn run_subscribe(&self, mut latest_block: u64) -> LibResult<()> {
+        loop {
+            if let Err(e) = self.subscribe(&mut latest_block).await {
+                tracing::error!("ws subscription err={e}, fall back to polling");
+            }
+            let deadline = Instant::now() + Duration::from_secs(consts::WS_RECONNECT_INTERVAL);
+            let (start_block, end_block) = self.block_range(latest_block).await;
+            latest_block = self.run_filter(start_block, end_block, Some(deadline)).await?;
+        }
+    }
+
+    /// Index a new range whenever a factory or oracle log arrives, returns once the socket
+    /// is closed.
+    ///
+    /// The notification only triggers the range, the logs are still read with `get_logs`
+    /// from the cursor so logs missed while reconnecting are filled in. A poll tick keeps
+    /// the cursor moving when there are no factory logs for a while.
+    async fn subscribe(&self, latest_block: &mut u64) -> LibResult<()> {
+        let ws_url = consts::WS_PROVIDER
+            .as_deref()
+            .ok_or_else(|| LibError::InternalError("env not found WS_PROVIDER".to_string()))?;
+        // aggregators added later are picked up by the poll tick until the next reconnect
+        let mut addresses = vec![self.factory_contract];
+        addresses.extend(PRICE.aggregators());
+        let (_provider, mut sub) = subscribe_logs(ws_url, addresses).await?;
+        tracing::info!("ws subscription started. latest block: {latest_block}");
+
+        let mut ticker = tokio::time::interval(Duration::from_secs(consts::POLL_INTERVAL));
+        loop {
+            tokio::select! {
+                re = sub.recv() => {
+                    if !is_new_log(re, *latest_block)? {
+                        continue;
+                    }
+                }
+                _ = ticker.tick() => {}
+            }
+            if let Err(e) = self.sync_head(latest_block).await {
+                tracing::error!("filter err: {}", e);
+            }
+        }
+    }
+
+    /// index every block after `latest_block` up to the head
+    async fn sync_head(&self, latest_block: &mut u64) -> LibResult<()> {
+        let head_block = self.head_block().await?;
+        while head_block > *latest_block {
+            let end_block = head_block.min(*latest_block + consts::MAX_BLOCK_RANGE);
+            self.filter(*latest_block + 1, end_block).await?;
+            *latest_block = end_block;
+        }
+        Ok(())
+    }
+}
+
+/// Whether a subscription message brings a log after `latest_block`, lagging counts as new
+/// logs. A closed subscription is an error, the indexer falls back to polling.
+fn is_new_log(re: Result<Log, RecvError>, latest_block: u64) -> LibResult<bool> {
+    match re {
+        Ok(log) => Ok(log.block_number.is_none_or(|v| v > latest_block)),
+        Err(RecvError::Lagged(n)) => {
+            tracing::warn!("ws subscription lagged {n} logs");
+            Ok(true)
+        }
+        Err(RecvError::Closed) => Err(LibError::InternalError(
+            "ws subscription closed".to_string(),
+        )),
+    }
+}
+
+/// Subscribe to the logs of `addresses`, the provider has to be kept alive with the subscription.
+async fn subscribe_logs(
+    url: &str,
+    addresses: Vec<Address>,
+) -> LibResult<(RootProvider<Ethereum>, Subscription<Log>)> {
+    let provider = RootProvider::<Ethereum>::connect(url).await?;
+    let sub = provider
+        .subscribe_logs(&Filter::new().address(addresses))
+        .await?;
+    Ok((provider, sub))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::node_bindings::Anvil;
+    use alloy::primitives::Bytes;
+    use alloy::rpc::types::TransactionRequest;
+
+    /// contract whose fallback emits an empty LOG0
+    const LOG_CONTRACT: &str = "0x6006600c60003960066000f360006000a000";
+
+    #[test]
+    fn t_is_new_log() {
+        let log = |block_number| Log {
+            block_number,
+            ..Default::default()
+        };
+        assert!(!is_new_log(Ok(log(Some(10))), 10).unwrap());
+        assert!(is_new_log(Ok(log(Some(11))), 10).unwrap());
+        assert!(is_new_log(Ok(log(None)), 10).unwrap());
+        assert!(is_new_log(Err(RecvError::Lagged(3)), 10).unwrap());
+        assert!(is_new_log(Err(RecvError::Closed), 10).is_err());
+    }
+
+    #[tokio::test]
+    async fn t_subscribe_logs_refused() {
+        // nothing listens on port 1, the indexer falls back to polling
+        let re = tokio::time::timeout(
+            Duration::from_secs(10),
+            subscribe_logs("ws://127.0.0.1:1", vec![Address::ZERO]),
+        )
+        .await
+        .unwrap();
+        assert!(re.is_err());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires anvil"]
+    async fn t_subscribe_logs() {
+        let anvil = Anvil::new().block_time(1).spawn();
+        let provider = RootProvider::<Ethereum>::new_http(anvil.endpoint_url());
+        let from = anvil.addresses()[0];
+
+        let deploy = TransactionRequest::default()
+            .from(from)
+            .input(LOG_CONTRACT.parse::<Bytes>().unwrap().into());
+        let receipt = provider
+            .send_transaction(deploy)
+            .await
+            .unwrap()
+            .get_receipt()
+            .await
+            .unwrap();
+        let contract = receipt.contract_address.unwrap();
+
+        let (_ws, mut sub) = subscribe_logs(&anvil.ws_endpoint(), vec![contract])
+            .await
+            .unwrap();
+        let call = TransactionRequest::default().from(from).to(contract);
+        let receipt = provider
+            .send_transaction(call)
+            .await
+            .unwrap()
+            .get_receipt()
+            .await
+            .unwrap();
+
+        let log = tokio::time::timeout(Duration::from_secs(10), sub.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(log.address(), contract);
+        assert_eq!(log.transaction_hash, Some(receipt.transaction_hash));
+        assert_eq!(log.block_number, receipt.block_number);
+    }
+}
@@ -1,4 +1,19 @@
+#[allow(clippy::module_inception)]
 mod evt;
+mod evt_admin;
+mod evt_block;
+mod evt_config;
+mod evt_failed;
+mod evt_kline;
+mod evt_logs;
+mod evt_oracle;
+mod evt_pair;
+mod evt_reorg;
 mod evt_trade;
+mod evt_transfer;
+mod evt_whitelist;
+mod evt_ws;
 
-pub use evt::Evt;
\ No newline at end of file
+pub use evt::Evt;
+pub use evt_kline::repair_klines;
+pub use evt_oracle::{backfill_rounds, reprice_trades, sync_prices};
\ No newline at end of file
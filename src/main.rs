@@ -4,6 +4,7 @@ mod util;
 mod core;
 mod svc;
 mod cron;
+mod cmd;
 
 #[tokio::main]
 async fn main() {
@@ -11,6 +12,15 @@ async fn main() {
     // init log 
     let store = core::pool::init_pool().await;
 
+    // operator command
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        if let Err(e) = cmd::run(store, &args).await {
+            tracing::error!("cmd err={e}")
+        }
+        return;
+    }
+
     // start cron time
     let cron_store = store.clone();
     tokio::spawn(async move {
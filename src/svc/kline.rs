@@ -0,0 +1,247 @@
+use rust_decimal::Decimal;
+use sea_orm::{ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, QueryOrder};
+
+use crate::core::consts;
+use crate::entity::{db_kline, db_kline_5m, db_token_summary};
+use crate::util::{LibError, LibResult, PeriodType};
+
+/// Candles of `token` for `period`, one for every bucket from `from_ts` to `to_ts`.
+///
+/// Buckets without trades carry the previous close, in the raised token and in USD, as open,
+/// high, low and close with zero volume. The series starts at the first candle when there is
+/// none before `from_ts`, spans of more than `KLINE_SERIES_MAX` candles are rejected.
+pub async fn kline_series(
+    db: &impl ConnectionTrait,
+    token: &str,
+    period: PeriodType,
+    from_ts: i64,
+    to_ts: i64,
+) -> LibResult<Vec<db_kline::Model>> {
+    let from_ts = period.open_ts(from_ts);
+    let prev = db_kline::Entity::find()
+        .filter(db_kline::Column::TokenAddress.eq(token))
+        .filter(db_kline::Column::Period.eq(period.name()))
+        .filter(db_kline::Column::OpenTs.lt(from_ts))
+        .order_by_desc(db_kline::Column::OpenTs)
+        .one(db)
+        .await?;
+    let candles = db_kline::Entity::find()
+        .filter(db_kline::Column::TokenAddress.eq(token))
+        .filter(db_kline::Column::Period.eq(period.name()))
+        .filter(db_kline::Column::OpenTs.between(from_ts, to_ts))
+        .order_by_asc(db_kline::Column::OpenTs)
+        .all(db)
+        .await?;
+    fill_gaps(
+        token,
+        period,
+        prev.map(|v| (v.close, v.close_usd)),
+        candles,
+        from_ts,
+        to_ts,
+    )
+}
+
+/// Market-cap candle in USD.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MarketCapCandle {
+    pub open_ts: i64,
+    pub close_ts: i64,
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+}
+
+/// Market-cap candles of `token`, the gap-filled USD candles of `kline_series` times the
+/// total supply in `token_summary`.
+pub async fn market_cap_series(
+    db: &impl ConnectionTrait,
+    token: &str,
+    period: PeriodType,
+    from_ts: i64,
+    to_ts: i64,
+) -> LibResult<Vec<MarketCapCandle>> {
+    let total_supply = db_token_summary::Entity::find_by_id(token)
+        .one(db)
+        .await?
+        .map(|v| v.total_supply)
+        .unwrap_or_default();
+    let series = kline_series(db, token, period, from_ts, to_ts).await?;
+    Ok(series
+        .iter()
+        .map(|v| MarketCapCandle {
+            open_ts: v.open_ts,
+            close_ts: v.close_ts,
+            open: v.open_usd * total_supply,
+            high: v.high_usd * total_supply,
+            low: v.low_usd * total_supply,
+            close: v.close_usd * total_supply,
+        })
+        .collect())
+}
+
+/// Close of the gap-filled `kline_5m` series of `token` at `ts`, the launch price when `ts` is
+/// before the first candle, `None` when the token has no candle.
+///
+/// `kline_5m` is the table of the 24h statistics, it has every candle `kline` may miss.
+pub async fn close_5m_at(
+    db: &impl ConnectionTrait,
+    token: &str,
+    ts: i64,
+) -> LibResult<Option<Decimal>> {
+    let last = db_kline_5m::Entity::find()
+        .filter(db_kline_5m::Column::TokenAddress.eq(token))
+        .filter(db_kline_5m::Column::OpenTs.lte(PeriodType::M5.open_ts(ts)))
+        .order_by_desc(db_kline_5m::Column::OpenTs)
+        .one(db)
+        .await?;
+    if let Some(last) = last {
+        return Ok(Some(last.close));
+    }
+    let first = db_kline_5m::Entity::find()
+        .filter(db_kline_5m::Column::TokenAddress.eq(token))
+        .order_by_asc(db_kline_5m::Column::OpenTs)
+        .one(db)
+        .await?;
+    Ok(first.map(|v| v.open))
+}
+
+/// Complete `candles`, ordered by `open_ts`, to one candle per bucket from `from_ts` to `to_ts`.
+///
+/// Without `prev_close` the series starts at the first candle. Fails when the series would
+/// have more than `KLINE_SERIES_MAX` candles.
+fn fill_gaps(
+    token: &str,
+    period: PeriodType,
+    prev_close: Option<(Decimal, Decimal)>,
+    candles: Vec<db_kline::Model>,
+    from_ts: i64,
+    to_ts: i64,
+) -> LibResult<Vec<db_kline::Model>> {
+    let mut series = Vec::new();
+    let mut open_ts = period.open_ts(from_ts);
+    if prev_close.is_none() {
+        match candles.first() {
+            Some(first) => open_ts = open_ts.max(period.open_ts(first.open_ts)),
+            None => return Ok(series),
+        }
+    }
+    let mut candles = candles.into_iter().peekable();
+    let mut close = prev_close;
+    while open_ts <= to_ts {
+        if series.len() == consts::KLINE_SERIES_MAX {
+            return Err(LibError::InternalError(format!(
+                "kline series longer than {} candles. token={token}, period={}, from_ts={from_ts}, to_ts={to_ts}",
+                consts::KLINE_SERIES_MAX,
+                period.name()
+            )));
+        }
+        // candles off the bucket grid, e.g. aligned in another offset, only pass on their close
+        while let Some(v) = candles.next_if(|v| v.open_ts < open_ts) {
+            close = Some((v.close, v.close_usd));
+        }
+        let close_ts = period.close_ts(open_ts);
+        match candles.next_if(|v| v.open_ts == open_ts) {
+            Some(v) => {
+                close = Some((v.close, v.close_usd));
+                series.push(v);
+            }
+            None => {
+                if let Some((close, close_usd)) = close {
+                    series.push(db_kline::Model {
+                        token_address: token.to_string(),
+                        period: period.name().to_string(),
+                        open_ts,
+                        close_ts,
+                        open: close,
+                        high: close,
+                        low: close,
+                        close,
+                        volume: Decimal::ZERO,
+                        amount: Decimal::ZERO,
+                        txn_num: 0,
+                        open_usd: close_usd,
+                        high_usd: close_usd,
+                        low_usd: close_usd,
+                        close_usd,
+                        amount_usd: Decimal::ZERO,
+                    });
+                }
+            }
+        }
+        open_ts = close_ts + 1;
+    }
+    Ok(series)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candle(open_ts: i64, open: i64, close: i64) -> db_kline::Model {
+        db_kline::Model {
+            token_address: "t".to_string(),
+            period: PeriodType::M5.name().to_string(),
+            open_ts,
+            close_ts: open_ts + 299,
+            open: Decimal::from(open),
+            high: Decimal::from(open.max(close)),
+            low: Decimal::from(open.min(close)),
+            close: Decimal::from(close),
+            volume: Decimal::ONE,
+            amount: Decimal::ONE,
+            txn_num: 1,
+            open_usd: Decimal::from(open * 2),
+            high_usd: Decimal::from(open.max(close) * 2),
+            low_usd: Decimal::from(open.min(close) * 2),
+            close_usd: Decimal::from(close * 2),
+            amount_usd: Decimal::TWO,
+        }
+    }
+
+    #[test]
+    fn t_fill_gaps() {
+        let period = PeriodType::M5;
+        let candles = vec![candle(600, 2, 3), candle(1500, 3, 5)];
+
+        // without an earlier close the series starts at the first candle
+        let series = fill_gaps("t", period, None, candles.clone(), 0, 1800).unwrap();
+        let open_ts: Vec<i64> = series.iter().map(|v| v.open_ts).collect();
+        assert_eq!(open_ts, vec![600, 900, 1200, 1500, 1800]);
+        let gap = &series[1];
+        assert_eq!(gap.close_ts, 1199);
+        assert_eq!(gap.open, Decimal::from(3));
+        assert_eq!(gap.high, Decimal::from(3));
+        assert_eq!(gap.low, Decimal::from(3));
+        assert_eq!(gap.close, Decimal::from(3));
+        assert_eq!(gap.open_usd, Decimal::from(6));
+        assert_eq!(gap.low_usd, Decimal::from(6));
+        assert_eq!(gap.close_usd, Decimal::from(6));
+        assert_eq!(gap.volume, Decimal::ZERO);
+        assert_eq!(gap.amount_usd, Decimal::ZERO);
+        assert_eq!(gap.txn_num, 0);
+        assert_eq!(series[3], candles[1]);
+        assert_eq!(series[4].close, Decimal::from(5));
+
+        // an earlier close fills the leading buckets
+        let prev_close = Some((Decimal::ONE, Decimal::TWO));
+        let series = fill_gaps("t", period, prev_close, candles.clone(), 310, 1000).unwrap();
+        assert_eq!(series.len(), 3);
+        assert_eq!(series[0].open_ts, 300);
+        assert_eq!(series[0].close, Decimal::ONE);
+        assert_eq!(series[0].close_usd, Decimal::TWO);
+        assert_eq!(series[1].volume, Decimal::ONE);
+        assert_eq!(series[2].open, Decimal::from(3));
+
+        // nothing to fill without any close
+        assert!(fill_gaps("t", period, None, Vec::new(), 0, 1800)
+            .unwrap()
+            .is_empty());
+
+        // spans of more candles than a series may have are rejected
+        let max_ts = 300 * consts::KLINE_SERIES_MAX as i64;
+        assert!(fill_gaps("t", period, prev_close, candles.clone(), 0, max_ts - 1).is_ok());
+        assert!(fill_gaps("t", period, prev_close, candles, 0, max_ts).is_err());
+    }
+}
@@ -1,3 +1,9 @@
+mod kline;
+mod notify;
+mod price;
 mod token;
 
+pub use kline::{close_5m_at, kline_series, market_cap_series};
+pub use notify::{Alert, Severity, NOTIFIER};
+pub use price::{OraclePrice, PriceCache, PRICE};
 pub use token::TOKEN;
@@ -0,0 +1,110 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::LazyLock;
+
+use alloy::transports::http::reqwest;
+use serde::Serialize;
+
+use crate::core::consts;
+use crate::util::{LibError, LibResult};
+
+pub static NOTIFIER: LazyLock<Notify> = LazyLock::new(Notify::new);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    High,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Alert {
+    pub severity: Severity,
+    pub title: String,
+    pub detail: String,
+}
+
+pub type NotifyFuture<'a> = Pin<Box<dyn Future<Output = LibResult<()>> + Send + 'a>>;
+
+/// A channel alerts are delivered to.
+pub trait Notifier: Send + Sync {
+    fn notify<'a>(&'a self, alert: &'a Alert) -> NotifyFuture<'a>;
+}
+
+/// Writes alerts to the application log.
+pub struct LogNotifier;
+
+impl Notifier for LogNotifier {
+    fn notify<'a>(&'a self, alert: &'a Alert) -> NotifyFuture<'a> {
+        Box::pin(async move {
+            match alert.severity {
+                Severity::High => tracing::error!("ALERT {}: {}", alert.title, alert.detail),
+                Severity::Info => tracing::info!("alert {}: {}", alert.title, alert.detail),
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Posts alerts as JSON `{"severity", "title", "detail"}` to a webhook.
+pub struct WebhookNotifier {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    fn notify<'a>(&'a self, alert: &'a Alert) -> NotifyFuture<'a> {
+        Box::pin(async move {
+            let resp = self
+                .client
+                .post(&self.url)
+                .json(alert)
+                .send()
+                .await
+                .map_err(|e| LibError::InternalError(format!("webhook err={e}")))?;
+            if !resp.status().is_success() {
+                return Err(LibError::InternalError(format!(
+                    "webhook status={}",
+                    resp.status()
+                )));
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Fans alerts out to every configured notifier.
+pub struct Notify {
+    notifiers: Vec<Box<dyn Notifier>>,
+}
+
+impl Notify {
+    fn new() -> Self {
+        let mut notifiers: Vec<Box<dyn Notifier>> = vec![Box::new(LogNotifier)];
+        if let Some(url) = consts::ALERT_WEBHOOK.clone() {
+            notifiers.push(Box::new(WebhookNotifier::new(url)));
+        }
+        Self { notifiers }
+    }
+
+    /// Deliver `alert` in the background, a failing notifier is logged and never fails
+    /// the caller.
+    pub fn send(&'static self, alert: Alert) {
+        tokio::spawn(async move {
+            for notifier in self.notifiers.iter() {
+                if let Err(e) = notifier.notify(&alert).await {
+                    tracing::error!("notify err={e}, alert={alert:?}");
+                }
+            }
+        });
+    }
+}
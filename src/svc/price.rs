@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{LazyLock, RwLock};
+
+use alloy::primitives::utils::format_units;
+use alloy::primitives::{Address, I256};
+use rust_decimal::Decimal;
+
+use crate::util::LibResult;
+
+pub static PRICE: LazyLock<PriceCache> = LazyLock::new(PriceCache::default);
+
+/// Latest oracle round of a raised token.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OraclePrice {
+    pub asset: String,
+    /// oracle proxy of the raised token
+    pub oracle: String,
+    /// aggregator behind the oracle proxy, it emits `AnswerUpdated`
+    pub aggregator: Address,
+    pub decimals: u8,
+    pub price: Decimal,
+    /// round of the aggregator, not of the proxy
+    pub round: u64,
+    pub updated_at: i64,
+}
+
+/// In-process USD prices of the raised tokens, keyed by raised token address.
+#[derive(Default)]
+pub struct PriceCache {
+    prices: RwLock<HashMap<String, OraclePrice>>,
+}
+
+impl PriceCache {
+    pub fn get(&self, asset: &str) -> Option<Decimal> {
+        self.prices.read().unwrap().get(asset).map(|v| v.price)
+    }
+
+    /// Store `price` unless a newer round is cached already, returns whether it was stored.
+    pub fn set(&self, price: OraclePrice) -> bool {
+        let mut prices = self.prices.write().unwrap();
+        match prices.get(&price.asset) {
+            Some(v) if v.aggregator == price.aggregator && v.round >= price.round => false,
+            _ => {
+                prices.insert(price.asset.clone(), price);
+                true
+            }
+        }
+    }
+
+    /// Cached prices of the raised tokens priced by `aggregator`.
+    pub fn by_aggregator(&self, aggregator: Address) -> Vec<OraclePrice> {
+        self.prices
+            .read()
+            .unwrap()
+            .values()
+            .filter(|v| v.aggregator == aggregator)
+            .cloned()
+            .collect()
+    }
+
+    /// USD price of a raw feed `answer` with `decimals`.
+    pub fn answer_price(answer: I256, decimals: u8) -> LibResult<Decimal> {
+        Ok(Decimal::from_str(&format_units(answer, decimals)?)?)
+    }
+
+    pub fn aggregators(&self) -> Vec<Address> {
+        let mut aggregators: Vec<Address> = self
+            .prices
+            .read()
+            .unwrap()
+            .values()
+            .map(|v| v.aggregator)
+            .collect();
+        aggregators.sort();
+        aggregators.dedup();
+        aggregators
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn oracle_price(asset: &str, aggregator: Address, round: u64) -> OraclePrice {
+        OraclePrice {
+            asset: asset.to_string(),
+            oracle: String::new(),
+            aggregator,
+            decimals: 8,
+            price: Decimal::ONE,
+            round,
+            updated_at: 0,
+        }
+    }
+
+    #[test]
+    fn t_price_cache() {
+        let cache = PriceCache::default();
+        let aggregator = Address::repeat_byte(1);
+        assert!(cache.set(oracle_price("a", aggregator, 5)));
+        assert!(cache.set(oracle_price("b", aggregator, 5)));
+        assert!(cache.set(oracle_price("c", Address::repeat_byte(2), 5)));
+        // an older round is ignored, a new aggregator replaces the entry
+        assert!(!cache.set(oracle_price("a", aggregator, 4)));
+        assert!(cache.set(oracle_price("b", Address::repeat_byte(3), 1)));
+        assert_eq!(cache.aggregators().len(), 3);
+        assert_eq!(cache.by_aggregator(aggregator).len(), 1);
+        assert_eq!(cache.get("a"), Some(Decimal::ONE));
+
+        // 2500.5 with 8 decimals
+        let answer = I256::try_from(250_050_000_000i64).unwrap();
+        assert_eq!(
+            PriceCache::answer_price(answer, 8).unwrap(),
+            Decimal::from_str("2500.5").unwrap()
+        );
+    }
+}
@@ -2,15 +2,18 @@ use std::ops::Div;
 use std::str::FromStr;
 use std::sync::LazyLock;
 
+use alloy::eips::BlockId;
 use alloy::network::Ethereum;
+use alloy::primitives::aliases::U80;
 use alloy::primitives::utils::{format_ether, format_units};
+use alloy::primitives::{Address, I256, U256};
 use alloy::providers::RootProvider;
 use rust_decimal::Decimal;
 
-use crate::core::consts;
+use crate::core::{consts, RPC};
 use crate::util::LibResult;
 
-pub static TOKEN: LazyLock<Token> = LazyLock::new(|| Token::new());
+pub static TOKEN: LazyLock<Token> = LazyLock::new(Token::new);
 
 pub struct Token {
     factory: consts::FACTORY::FACTORYInstance<(), RootProvider>,
@@ -19,39 +22,93 @@ pub struct Token {
 
 impl Token {
     pub fn new() -> Self {
-        let url = consts::PROVIDER
-            .parse()
-            .expect("Could not parse the Provider URL");
         let factory_address = consts::FACTORY_CONTRACT_ADDR
             .parse()
             .expect("Could not parse the Factory Address");
-        let provider = RootProvider::new_http(url);
+        let provider = RPC.provider();
         let factory = consts::FACTORY::new(factory_address, provider.clone());
         Self { provider, factory }
     }
 
-    pub async fn balance_of(&self, token: &str, user: &str) -> LibResult<Decimal> {
+    /// Reads take an optional `block`, state is read as of that block, or of the latest
+    /// block if it is `None`.
+    pub async fn balance_of(
+        &self,
+        token: &str,
+        user: &str,
+        block: Option<BlockId>,
+    ) -> LibResult<Decimal> {
         let token_address = token.parse()?;
         let user_address = user.parse()?;
         let contract = consts::ERC20::new(token_address, self.provider.clone());
-        let balance = contract.balanceOf(user_address).call().await?._0;
+        let balance = contract
+            .balanceOf(user_address)
+            .block(block.unwrap_or_default())
+            .call()
+            .await?
+            ._0;
         let amount = Decimal::from_str(&format_ether(balance))?;
         Ok(amount)
     }
 
-    pub async fn total_supply(&self, token: &str) -> LibResult<Decimal> {
+    /// `(name, symbol, decimals)` of an ERC20 token
+    pub async fn erc20_info(
+        &self,
+        token: &str,
+        block: Option<BlockId>,
+    ) -> LibResult<(String, String, u8)> {
+        let token_address = token.parse()?;
+        let block = block.unwrap_or_default();
+        let contract = consts::ERC20::new(token_address, self.provider.clone());
+        let name = contract.name().block(block).call().await?._0;
+        let symbol = contract.symbol().block(block).call().await?._0;
+        let decimals = contract.decimals().block(block).call().await?._0;
+        Ok((name, symbol, decimals))
+    }
+
+    /// `(reserve0, reserve1)` of a Uniswap V2 pair
+    pub async fn pair_reserves(
+        &self,
+        pair: &str,
+        block: Option<BlockId>,
+    ) -> LibResult<(U256, U256)> {
+        let pair_address = pair.parse()?;
+        let contract = consts::PAIR::new(pair_address, self.provider.clone());
+        let reserves = contract
+            .getReserves()
+            .block(block.unwrap_or_default())
+            .call()
+            .await?;
+        Ok((
+            U256::from(reserves._reserve0),
+            U256::from(reserves._reserve1),
+        ))
+    }
+
+    pub async fn total_supply(&self, token: &str, block: Option<BlockId>) -> LibResult<Decimal> {
         let token_address = token.parse()?;
         let contract = consts::ERC20::new(token_address, self.provider.clone());
-        let balance = contract.totalSupply().call().await?._0;
+        let balance = contract
+            .totalSupply()
+            .block(block.unwrap_or_default())
+            .call()
+            .await?
+            ._0;
         let amount = Decimal::from_str(&format_ether(balance))?;
         Ok(amount)
     }
 
-    pub async fn curve_process(&self, token: &str) -> LibResult<(Decimal, Decimal)> {
+    pub async fn curve_process(
+        &self,
+        token: &str,
+        block: Option<BlockId>,
+    ) -> LibResult<(Decimal, Decimal)> {
         let token_address = token.parse()?;
+        let block = block.unwrap_or_default();
         let now_point = self
             .factory
             .getTokenSoldAmount(token_address)
+            .block(block)
             .call()
             .await?
             ._0;
@@ -59,6 +116,7 @@ impl Token {
         let end_point = self
             .factory
             .getTokenTotalSalesAmount(token_address)
+            .block(block)
             .call()
             .await?
             ._0;
@@ -67,14 +125,100 @@ impl Token {
         Ok((process, liquidity_token))
     }
 
-    pub async fn oracle_price(&self, token: &str) -> LibResult<Decimal> {
+    pub async fn oracle_price(&self, token: &str, block: Option<BlockId>) -> LibResult<Decimal> {
         let token_address = token.parse()?;
+        let block = block.unwrap_or_default();
         let contract = consts::ORACLE::new(token_address, self.provider.clone());
-        let answer = contract.latestAnswer().call().await?._0;
-        let decimal = contract.decimals().call().await?._0;
+        let answer = contract.latestAnswer().block(block).call().await?._0;
+        let decimal = contract.decimals().block(block).call().await?._0;
         let price = Decimal::from_str(&format_units(answer, decimal)?)?;
         Ok(price)
     }
+
+    /// Latest round of an oracle proxy as `(aggregator, decimals, answer, round, updated_at)`,
+    /// the round is the one of the aggregator, as in its `AnswerUpdated` events.
+    pub async fn oracle_latest(&self, oracle: &str) -> LibResult<(Address, u8, I256, u64, i64)> {
+        let oracle_address = oracle.parse()?;
+        let contract = consts::ORACLE::new(oracle_address, self.provider.clone());
+        let aggregator = contract.aggregator().call().await?._0;
+        let decimals = contract.decimals().call().await?._0;
+        let data = contract.latestRoundData().call().await?;
+        // proxy round ids carry the phase in the upper bits
+        let round = data.roundId.as_limbs()[0];
+        Ok((
+            aggregator,
+            decimals,
+            data.answer,
+            round,
+            data.updatedAt.to::<i64>(),
+        ))
+    }
+
+    /// Current phase of an oracle proxy and its decimals.
+    pub async fn oracle_phase(&self, oracle: &str) -> LibResult<(u16, u8)> {
+        let oracle_address = oracle.parse()?;
+        let contract = consts::ORACLE::new(oracle_address, self.provider.clone());
+        let phase = contract.phaseId().call().await?._0;
+        let decimals = contract.decimals().call().await?._0;
+        Ok((phase, decimals))
+    }
+
+    /// Aggregator of `phase` of an oracle proxy and its latest round.
+    pub async fn phase_aggregator(&self, oracle: &str, phase: u16) -> LibResult<(Address, u64)> {
+        let oracle_address = oracle.parse()?;
+        let contract = consts::ORACLE::new(oracle_address, self.provider.clone());
+        let aggregator = contract.phaseAggregators(phase).call().await?._0;
+        let aggregator_contract = consts::ORACLE::new(aggregator, self.provider.clone());
+        let round = aggregator_contract.latestRound().call().await?._0;
+        Ok((aggregator, round.to::<u64>()))
+    }
+
+    /// `(answer, updated_at)` of `round` of `phase` read through the oracle proxy, `None` for
+    /// a round that does not exist.
+    pub async fn oracle_round(
+        &self,
+        oracle: &str,
+        phase: u16,
+        round: u64,
+    ) -> LibResult<Option<(I256, i64)>> {
+        let oracle_address = oracle.parse()?;
+        let contract = consts::ORACLE::new(oracle_address, self.provider.clone());
+        let round_id = (U80::from(phase) << 64) | U80::from(round);
+        let data = match contract.getRoundData(round_id).call().await {
+            Ok(v) => v,
+            // proxies revert with "No data present" for unknown rounds
+            Err(alloy::contract::Error::TransportError(e)) if e.is_error_resp() => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if data.updatedAt.is_zero() {
+            return Ok(None);
+        }
+        Ok(Some((data.answer, data.updatedAt.to::<i64>())))
+    }
+
+    /// Factory parameters as `(param, value)`, read from the view functions.
+    pub async fn factory_config(
+        &self,
+        block: Option<BlockId>,
+    ) -> LibResult<Vec<(&'static str, String)>> {
+        let block = block.unwrap_or_default();
+        let buy_fee = self.factory.getBuyFee().block(block).call().await?._0;
+        let sell_fee = self.factory.getSellFee().block(block).call().await?._0;
+        let migration_tax = self.factory.getMigrationTax().block(block).call().await?._0;
+        let tax_vault = self.factory.taxVault().block(block).call().await?._0;
+        let signer = self.factory.signer().block(block).call().await?._0;
+        let max_fun_curve = self.factory.maxFunCurve().block(block).call().await?._0;
+        let manager = self.factory.manager().block(block).call().await?._0;
+        Ok(vec![
+            ("buy_fee", buy_fee.to_string()),
+            ("sell_fee", sell_fee.to_string()),
+            ("migration_tax", migration_tax.to_string()),
+            ("tax_vault", format!("{tax_vault:#x}")),
+            ("signer", format!("{signer:#x}")),
+            ("max_fun_curve", format!("{max_fun_curve:#x}")),
+            ("max_fun_manager", format!("{manager:#x}")),
+        ])
+    }
 }
 
 #[cfg(test)]
@@ -88,6 +232,7 @@ mod tests {
             .balance_of(
                 "0xB2284B8eee1E364F6bD4fA814e64303819a16aE8",
                 "0xF41BBb59B4291Ae8711ef276DdC0a26E6AD0137C",
+                None,
             )
             .await;
         assert!(balance.is_ok());
@@ -97,7 +242,7 @@ mod tests {
     async fn test_oracle_price() {
         dotenvy::dotenv().expect("");
         let price = TOKEN
-            .oracle_price("0xB72052E8FaCC4c032a55d653007F60f95dBbA525")
+            .oracle_price("0xB72052E8FaCC4c032a55d653007F60f95dBbA525", None)
             .await;
         assert!(price.is_ok());
     }
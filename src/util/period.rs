@@ -1,54 +1,298 @@
+use std::str::FromStr;
+
+use chrono::{Datelike, NaiveDate};
 use sea_orm::EnumIter;
 
+use crate::core::consts;
+use crate::util::LibError;
+
+const DAY: i64 = 86400;
+const WEEK: i64 = 7 * DAY;
+/// 1970-01-01 was a Thursday, weeks start on the Monday 3 days before
+const WEEK_SHIFT: i64 = 3 * DAY;
 
-#[derive(Copy, Clone, Debug, EnumIter)]
+#[derive(Copy, Clone, Debug, PartialEq, EnumIter)]
 pub enum PeriodType {
     // 10秒钟
     S10,
+    // 1分钟
+    M1,
     // 5分钟
     M5,
+    // 15分钟
+    M15,
     // 30分钟
     M30,
+    // 1小时
+    H1,
     // 2小时
     H2,
+    // 4小时
+    H4,
     // 1天
     D1,
-    // // 1周
+    // 1周, 从周一开始
+    W1,
+    // 1月, 自然月
+    MN1,
 }
 
 impl PeriodType {
+    /// Open time of the candle containing `ts`, aligned in `KLINE_TZ_OFFSET`.
     pub fn open_ts(&self, ts: i64) -> i64 {
-        ts - ts % self.period()
+        self.open_ts_tz(ts, *consts::KLINE_TZ_OFFSET)
+    }
+
+    /// Last second of the candle containing `ts`, aligned in `KLINE_TZ_OFFSET`.
+    pub fn close_ts(&self, ts: i64) -> i64 {
+        self.close_ts_tz(ts, *consts::KLINE_TZ_OFFSET)
+    }
+
+    /// Open time of the candle containing `ts`, aligned in local time `offset` seconds east of UTC.
+    pub fn open_ts_tz(&self, ts: i64, offset: i64) -> i64 {
+        let local = ts + offset;
+        let open = match self {
+            PeriodType::W1 => local - (local + WEEK_SHIFT).rem_euclid(WEEK),
+            PeriodType::MN1 => month_start(local, 0),
+            _ => local - local.rem_euclid(self.seconds()),
+        };
+        open - offset
     }
-    // pub fn last_open_ts(&self, ts: i64) -> i64 {
-    //     self.open_ts(ts) - self.period()
-    // }
 
-    pub fn period(&self) -> i64 {
+    /// Last second of the candle containing `ts`, aligned in local time `offset` seconds east of UTC.
+    pub fn close_ts_tz(&self, ts: i64, offset: i64) -> i64 {
+        let open = self.open_ts_tz(ts, offset);
+        let next_open = match self {
+            PeriodType::MN1 => month_start(open + offset, 1) - offset,
+            _ => open + self.seconds(),
+        };
+        next_open - 1
+    }
+
+    /// Length in seconds of the fixed-length periods.
+    fn seconds(&self) -> i64 {
         match self {
             PeriodType::S10 => 10,
+            PeriodType::M1 => 60,
             PeriodType::M5 => 300,
+            PeriodType::M15 => 900,
             PeriodType::M30 => 1800,
+            PeriodType::H1 => 3600,
             PeriodType::H2 => 7200,
-            PeriodType::D1 => 86400,
+            PeriodType::H4 => 14400,
+            PeriodType::D1 => DAY,
+            PeriodType::W1 => WEEK,
+            // months follow the calendar, see `month_start`
+            PeriodType::MN1 => 31 * DAY,
+        }
+    }
+
+    /// SQL expression of `open_ts` of the unix time in `column`, for bulk candle queries.
+    pub fn open_ts_sql(&self, column: &str) -> String {
+        self.open_ts_sql_tz(column, *consts::KLINE_TZ_OFFSET)
+    }
+
+    /// SQL expression of `close_ts` of the unix time in `column`, for bulk candle queries.
+    pub fn close_ts_sql(&self, column: &str) -> String {
+        self.close_ts_sql_tz(column, *consts::KLINE_TZ_OFFSET)
+    }
+
+    /// SQL expression of `open_ts_tz` of the unix time in `column`.
+    fn open_ts_sql_tz(&self, column: &str, offset: i64) -> String {
+        match self {
+            PeriodType::W1 => format!(
+                "({column} + {offset}) - ({column} + {offset} + {WEEK_SHIFT}) % {WEEK} - {offset}"
+            ),
+            PeriodType::MN1 => format!(
+                "(EXTRACT(EPOCH FROM DATE_TRUNC('month', TO_TIMESTAMP({column} + {offset}) AT TIME ZONE 'UTC'))::BIGINT - {offset})"
+            ),
+            _ => format!(
+                "({column} + {offset}) - ({column} + {offset}) % {} - {offset}",
+                self.seconds()
+            ),
+        }
+    }
+
+    /// SQL expression of `close_ts_tz` of the unix time in `column`.
+    fn close_ts_sql_tz(&self, column: &str, offset: i64) -> String {
+        match self {
+            PeriodType::MN1 => format!(
+                "(EXTRACT(EPOCH FROM DATE_TRUNC('month', TO_TIMESTAMP({column} + {offset}) AT TIME ZONE 'UTC') + INTERVAL '1 month')::BIGINT - {offset} - 1)"
+            ),
+            _ => format!(
+                "({} + {} - 1)",
+                self.open_ts_sql_tz(column, offset),
+                self.seconds()
+            ),
+        }
+    }
+
+    /// name stored in the `period` column of `kline`
+    pub fn name(&self) -> &'static str {
+        match self {
+            PeriodType::S10 => "S10",
+            PeriodType::M1 => "M1",
+            PeriodType::M5 => "M5",
+            PeriodType::M15 => "M15",
+            PeriodType::M30 => "M30",
+            PeriodType::H1 => "H1",
+            PeriodType::H2 => "H2",
+            PeriodType::H4 => "H4",
+            PeriodType::D1 => "D1",
+            PeriodType::W1 => "W1",
+            PeriodType::MN1 => "MN1",
         }
     }
 
-    pub fn close_ts(&self, open_ts: i64) -> i64 {
-        open_ts + self.period() - 1
+    /// TradingView resolution string, parsed back by `from_str`.
+    pub fn resolution(&self) -> &'static str {
+        match self {
+            PeriodType::S10 => "10S",
+            PeriodType::M1 => "1",
+            PeriodType::M5 => "5",
+            PeriodType::M15 => "15",
+            PeriodType::M30 => "30",
+            PeriodType::H1 => "1H",
+            PeriodType::H2 => "2H",
+            PeriodType::H4 => "4H",
+            PeriodType::D1 => "1D",
+            PeriodType::W1 => "1W",
+            PeriodType::MN1 => "1M",
+        }
     }
 }
 
+/// Parses TradingView resolutions: minutes as a bare number ("15", "60"), or a count with
+/// `S`, `H`, `D`, `W` or `M` (month), the count defaults to 1 ("D", "W", "M").
+impl FromStr for PeriodType {
+    type Err = LibError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let period = match s {
+            "10S" => PeriodType::S10,
+            "1" => PeriodType::M1,
+            "5" => PeriodType::M5,
+            "15" => PeriodType::M15,
+            "30" => PeriodType::M30,
+            "60" | "1H" => PeriodType::H1,
+            "120" | "2H" => PeriodType::H2,
+            "240" | "4H" => PeriodType::H4,
+            "D" | "1D" => PeriodType::D1,
+            "W" | "1W" => PeriodType::W1,
+            "M" | "1M" => PeriodType::MN1,
+            _ => {
+                return Err(LibError::InternalError(format!(
+                    "unsupported resolution {s}"
+                )))
+            }
+        };
+        Ok(period)
+    }
+}
+
+/// Unix time of the first day of the month `months` after the month of `ts`, both in UTC.
+fn month_start(ts: i64, months: u32) -> i64 {
+    let date = chrono::DateTime::from_timestamp(ts, 0)
+        .unwrap_or_default()
+        .date_naive();
+    let month0 = date.month0() + months;
+    NaiveDate::from_ymd_opt(date.year() + (month0 / 12) as i32, month0 % 12 + 1, 1)
+        .unwrap_or_default()
+        .and_hms_opt(0, 0, 0)
+        .unwrap_or_default()
+        .and_utc()
+        .timestamp()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use sea_orm::{ConnectionTrait, Iterable};
+
+    const EAST8: i64 = 8 * 3600;
 
     #[test]
     fn t_alias_open_ts() {
-        assert_eq!(PeriodType::S10.open_ts(1702696269), 1702696200);
-        // assert_eq!(alias_open_ts(1702696269, super::PeriodType::Min15), 1702695600);
-        // assert_eq!(alias_open_ts(1702696269, super::PeriodType::H1), 1702695600);
-        // assert_eq!(alias_open_ts(1702696269, super::PeriodType::D1), 1702684800);
-        // assert_eq!(alias_open_ts(1702696269, super::PeriodType::W1), 1702252800);
+        // 2023-12-16 03:11:09 UTC, 11:11:09 in UTC+8
+        let ts = 1702696269;
+        assert_eq!(PeriodType::S10.open_ts_tz(ts, EAST8), 1702696260);
+        assert_eq!(PeriodType::M15.open_ts_tz(ts, EAST8), 1702695600);
+        assert_eq!(PeriodType::H1.open_ts_tz(ts, EAST8), 1702695600);
+        assert_eq!(PeriodType::H4.open_ts_tz(ts, EAST8), 1702684800);
+        // 2023-12-16 00:00 UTC+8, 2023-12-16 00:00 UTC
+        assert_eq!(PeriodType::D1.open_ts_tz(ts, EAST8), 1702656000);
+        assert_eq!(PeriodType::D1.open_ts_tz(ts, 0), 1702684800);
+        // Monday 2023-12-11 00:00
+        assert_eq!(PeriodType::W1.open_ts_tz(ts, EAST8), 1702224000);
+        assert_eq!(PeriodType::W1.open_ts_tz(ts, 0), 1702252800);
+        // 2023-12-01 00:00 UTC+8
+        assert_eq!(PeriodType::MN1.open_ts_tz(ts, EAST8), 1701360000);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn t_close_ts() {
+        let ts = 1702696269;
+        assert_eq!(PeriodType::M5.close_ts_tz(ts, EAST8), 1702696499);
+        assert_eq!(PeriodType::W1.close_ts_tz(ts, 0), 1702857599);
+        // last second of 2023-12-31 UTC+8, and of a leap February
+        assert_eq!(PeriodType::MN1.close_ts_tz(ts, EAST8), 1704038399);
+        assert_eq!(PeriodType::MN1.close_ts_tz(1709164800, 0), 1709251199);
+        // 2023-12-31 17:00 UTC is already January in UTC+8
+        assert_eq!(PeriodType::MN1.open_ts_tz(1704042000, EAST8), 1704038400);
+    }
+
+    #[test]
+    fn t_resolution() {
+        for period in PeriodType::iter() {
+            assert_eq!(period.resolution().parse::<PeriodType>().unwrap(), period);
+        }
+        assert_eq!("60".parse::<PeriodType>().unwrap(), PeriodType::H1);
+        assert_eq!("D".parse::<PeriodType>().unwrap(), PeriodType::D1);
+        assert_eq!("1M".parse::<PeriodType>().unwrap(), PeriodType::MN1);
+        assert!("1m".parse::<PeriodType>().is_err());
+        assert!("3".parse::<PeriodType>().is_err());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires postgres"]
+    async fn t_sql_matches_tz() {
+        dotenvy::dotenv().ok();
+        let db = sea_orm::Database::connect(consts::PG_URL.as_str())
+            .await
+            .unwrap();
+        // 2023-12-16 03:11:09 UTC, the last second of 2023 in UTC+8, a leap February, and
+        // the start of a Monday, in UTC, UTC+8 and UTC-5
+        let times = [1702696269, 1704038399, 1709164800, 1709251199, 1702252800];
+        for offset in [0, EAST8, -5 * 3600] {
+            for period in PeriodType::iter() {
+                for ts in times {
+                    let sql = format!(
+                        "SELECT {} AS open_ts, {} AS close_ts FROM (SELECT $1::BIGINT AS ts) t",
+                        period.open_ts_sql_tz("ts", offset),
+                        period.close_ts_sql_tz("ts", offset)
+                    );
+                    let row = db
+                        .query_one(sea_orm::Statement::from_sql_and_values(
+                            sea_orm::DbBackend::Postgres,
+                            sql,
+                            [ts.into()],
+                        ))
+                        .await
+                        .unwrap()
+                        .unwrap();
+                    let open_ts: i64 = row.try_get("", "open_ts").unwrap();
+                    let close_ts: i64 = row.try_get("", "close_ts").unwrap();
+                    assert_eq!(
+                        (open_ts, close_ts),
+                        (
+                            period.open_ts_tz(ts, offset),
+                            period.close_ts_tz(ts, offset)
+                        ),
+                        "period={}, ts={ts}, offset={offset}",
+                        period.name()
+                    );
+                }
+            }
+        }
+    }
+}